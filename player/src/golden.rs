@@ -0,0 +1,200 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/*! Golden-image regression testing on top of trace replay.
+ *
+ * A trace is replayed headlessly, then the designated render targets are read
+ * back and compared against the reference images with a per-channel tolerance.
+ * The references are stored as raw, tightly packed texels in the format of
+ * the corresponding texture.
+!*/
+
+use crate::{gfx_select, GlobalExt as _, IdentityPassThroughFactory, TraceError};
+use wgc::{device::trace, id::TypedId as _};
+
+use std::{fs, io, path::Path, path::PathBuf};
+
+/// A render target to check after the replay.
+#[derive(Clone, Debug)]
+pub struct Expectation {
+    /// Texture to read back. It needs to be created with `COPY_SRC` usage.
+    pub texture: wgc::id::TextureId,
+    /// Size of the texture, in texels.
+    pub size: wgt::Extent3d,
+    /// Number of bytes in a single texel of the texture format.
+    pub bytes_per_texel: u32,
+    /// Path to the reference data.
+    pub reference: PathBuf,
+    /// Maximum allowed difference for each channel byte.
+    pub tolerance: u8,
+}
+
+/// The reason an expectation failed.
+#[derive(Debug)]
+pub enum Mismatch {
+    /// The reference data couldn't be loaded.
+    MissingReference(io::Error),
+    /// The reference data doesn't have the same size as the texture contents.
+    WrongSize { expected: usize, actual: usize },
+    /// A channel value differs from the reference by more than the tolerance.
+    Difference {
+        offset: usize,
+        expected: u8,
+        actual: u8,
+    },
+}
+
+/// Compare `actual` data against the `expected` one, allowing each byte to differ by `tolerance`.
+pub fn compare(actual: &[u8], expected: &[u8], tolerance: u8) -> Result<(), Mismatch> {
+    if actual.len() != expected.len() {
+        return Err(Mismatch::WrongSize {
+            expected: expected.len(),
+            actual: actual.len(),
+        });
+    }
+    match actual
+        .iter()
+        .zip(expected)
+        .position(|(&a, &e)| (a as i16 - e as i16).abs() > tolerance as i16)
+    {
+        Some(offset) => Err(Mismatch::Difference {
+            offset,
+            expected: expected[offset],
+            actual: actual[offset],
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Replay the trace in `dir` and check all the `expectations` against the results.
+///
+/// Returns the failed expectations along with the reason of the failure.
+pub fn check_trace<'a>(
+    dir: &Path,
+    expectations: &'a [Expectation],
+) -> Result<Vec<(&'a Expectation, Mismatch)>, TraceError> {
+    let mut actions = crate::load_trace(dir)?;
+    actions.reverse(); // allows us to pop from the top
+
+    // Read-back buffers are placed after all the buffers created by the trace.
+    let first_free_buffer = actions
+        .iter()
+        .filter_map(|action| match *action {
+            trace::Action::CreateBuffer { id, .. } => Some(id.unzip().0 + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    let global =
+        wgc::hub::Global::new("golden", IdentityPassThroughFactory, wgt::BackendBit::all());
+    let mut command_buffer_id_manager = wgc::hub::IdentityManager::default();
+
+    let device = match actions.pop() {
+        Some(trace::Action::Init { desc, backend }) => {
+            let adapter = global
+                .pick_adapter(
+                    &wgc::instance::RequestAdapterOptions {
+                        power_preference: wgt::PowerPreference::Default,
+                        compatible_surface: None,
+                        force_fallback_adapter: false,
+                    },
+                    wgc::instance::AdapterInputs::IdSet(
+                        &[wgc::id::TypedId::zip(0, 0, backend)],
+                        |id| id.backend(),
+                    ),
+                )
+                .ok_or(TraceError::NoAdapter(backend))?;
+            gfx_select!(adapter => global.adapter_request_device(
+                adapter,
+                &desc,
+                None,
+                wgc::id::TypedId::zip(1, 0, wgt::Backend::Empty)
+            ))
+            .map_err(TraceError::RequestDevice)?
+        }
+        _ => return Err(TraceError::MissingInit),
+    };
+
+    let dir = dir.to_path_buf();
+    while let Some(action) = actions.pop() {
+        gfx_select!(device => global.process(device, action, &dir, &mut command_buffer_id_manager))?;
+    }
+
+    let mut failures = Vec::new();
+    for (i, expectation) in expectations.iter().enumerate() {
+        let buffer = wgc::id::TypedId::zip(first_free_buffer + i as u32, 0, device.backend());
+        let actual = gfx_select!(device => global.read_texture(
+            device,
+            expectation.texture,
+            expectation.size,
+            expectation.bytes_per_texel,
+            buffer,
+            &mut command_buffer_id_manager
+        ))?;
+        let result = match fs::read(&expectation.reference) {
+            Ok(expected) => compare(&actual, &expected, expectation.tolerance),
+            Err(e) => Err(Mismatch::MissingReference(e)),
+        };
+        if let Err(mismatch) = result {
+            failures.push((expectation, mismatch));
+        }
+    }
+    Ok(failures)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn malformed_trace() {
+        let dir = std::env::temp_dir().join("wgpu-golden-malformed");
+        fs::create_dir_all(&dir).unwrap();
+        let text = format!("{}{}\n[\n    Init(\n", trace::HEADER_PREFIX, trace::VERSION);
+        fs::write(dir.join(trace::FILE_NAME), text).unwrap();
+        let result = check_trace(&dir, &[]);
+        fs::remove_dir_all(&dir).unwrap();
+        match result {
+            Err(TraceError::Parse(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn compare_size_mismatch() {
+        match compare(&[0; 4], &[0; 3], 0) {
+            Err(Mismatch::WrongSize {
+                expected: 3,
+                actual: 4,
+            }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn compare_tolerance_boundary() {
+        assert!(compare(&[10, 20], &[13, 17], 3).is_ok());
+        match compare(&[10, 20], &[14, 17], 3) {
+            Err(Mismatch::Difference {
+                offset: 0,
+                expected: 14,
+                actual: 10,
+            }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn compare_first_difference() {
+        match compare(&[0, 5, 0, 9], &[0, 0, 0, 0], 1) {
+            Err(Mismatch::Difference {
+                offset: 1,
+                expected: 0,
+                actual: 5,
+            }) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
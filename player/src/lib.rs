@@ -0,0 +1,724 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/*! Replaying of WebGPU traces, shared by the player and the golden tests.
+ *
+ * # Notes
+ * - we call device_maintain_ids() before creating any refcounted resource,
+ *   which is basically everything except for BGL and shader modules,
+ *   so that we don't accidentally try to use the same ID.
+!*/
+
+use wgc::device::trace;
+
+use std::{
+    ffi::CString,
+    fmt::{self, Debug},
+    fs, io,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    ptr,
+};
+
+pub mod golden;
+
+#[macro_export]
+macro_rules! gfx_select {
+    ($id:expr => $global:ident.$method:ident( $($param:expr),+ )) => {
+        match $id.backend() {
+            #[cfg(not(any(target_os = "ios", target_os = "macos")))]
+            wgt::Backend::Vulkan => $global.$method::<wgc::backend::Vulkan>( $($param),+ ),
+            #[cfg(any(target_os = "ios", target_os = "macos"))]
+            wgt::Backend::Metal => $global.$method::<wgc::backend::Metal>( $($param),+ ),
+            #[cfg(windows)]
+            wgt::Backend::Dx12 => $global.$method::<wgc::backend::Dx12>( $($param),+ ),
+            #[cfg(windows)]
+            wgt::Backend::Dx11 => $global.$method::<wgc::backend::Dx11>( $($param),+ ),
+            _ => unreachable!()
+        }
+    };
+}
+
+/// The reason a trace couldn't be replayed.
+#[derive(Debug)]
+pub enum TraceError {
+    /// The trace file couldn't be opened.
+    Io(io::Error),
+    /// The trace file couldn't be parsed.
+    Parse(ron::de::Error),
+    /// The trace doesn't start with `Action::Init`.
+    MissingInit,
+    /// The trace format version is not supported by this build.
+    IncompatibleVersion(u32),
+    /// No adapter is available for the backend the trace was recorded on.
+    NoAdapter(wgt::Backend),
+    /// The device described in the trace couldn't be created.
+    RequestDevice(wgc::instance::RequestDeviceError),
+    /// The action can't be replayed without a window.
+    UnexpectedAction(&'static str),
+    /// The swap chain didn't provide a texture.
+    NoSwapChainTexture,
+    /// A data file referenced by the trace couldn't be read.
+    Data(io::Error),
+    /// The read-back buffer couldn't be mapped.
+    MapReadBack(String),
+    /// A bind group layout couldn't be created.
+    BindGroupLayout(wgc::binding_model::BindGroupLayoutError),
+    /// A pipeline layout couldn't be created.
+    PipelineLayout(wgc::binding_model::PipelineLayoutError),
+    /// A bind group couldn't be created.
+    BindGroup(wgc::binding_model::BindGroupError),
+    /// A compute pipeline couldn't be created.
+    ComputePipeline(wgc::pipeline::ComputePipelineError),
+    /// A render pipeline couldn't be created.
+    RenderPipeline(wgc::pipeline::RenderPipelineError),
+    /// A transfer command couldn't be encoded.
+    Transfer(wgc::command::TransferError),
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceError::Io(e) => write!(f, "Unable to read the trace: {}", e),
+            TraceError::Parse(e) => write!(f, "Unable to parse the trace: {}", e),
+            TraceError::MissingInit => write!(f, "Expected Action::Init"),
+            TraceError::IncompatibleVersion(version) => write!(
+                f,
+                "Trace format version {} is not supported, expected {}..={}",
+                version,
+                trace::MIN_COMPATIBLE_VERSION,
+                trace::VERSION
+            ),
+            TraceError::NoAdapter(backend) => {
+                write!(f, "Unable to find an adapter for {:?} backend", backend)
+            }
+            TraceError::RequestDevice(e) => write!(f, "Failed to request device: {}", e),
+            TraceError::UnexpectedAction(reason) => write!(f, "Unexpected action: {}", reason),
+            TraceError::NoSwapChainTexture => write!(f, "Unable to get the swap chain texture"),
+            TraceError::Data(e) => write!(f, "Unable to read the trace data: {}", e),
+            TraceError::MapReadBack(status) => {
+                write!(f, "Unable to map the read-back buffer: {}", status)
+            }
+            TraceError::BindGroupLayout(e) => {
+                write!(f, "Failed to create a bind group layout: {:?}", e)
+            }
+            TraceError::PipelineLayout(e) => {
+                write!(f, "Failed to create a pipeline layout: {:?}", e)
+            }
+            TraceError::BindGroup(e) => write!(f, "Failed to create a bind group: {:?}", e),
+            TraceError::ComputePipeline(e) => {
+                write!(f, "Failed to create a compute pipeline: {:?}", e)
+            }
+            TraceError::RenderPipeline(e) => {
+                write!(f, "Failed to create a render pipeline: {:?}", e)
+            }
+            TraceError::Transfer(e) => write!(f, "Failed to encode a transfer: {:?}", e),
+        }
+    }
+}
+
+impl From<wgc::binding_model::BindGroupLayoutError> for TraceError {
+    fn from(error: wgc::binding_model::BindGroupLayoutError) -> Self {
+        TraceError::BindGroupLayout(error)
+    }
+}
+
+impl From<wgc::binding_model::PipelineLayoutError> for TraceError {
+    fn from(error: wgc::binding_model::PipelineLayoutError) -> Self {
+        TraceError::PipelineLayout(error)
+    }
+}
+
+impl From<wgc::binding_model::BindGroupError> for TraceError {
+    fn from(error: wgc::binding_model::BindGroupError) -> Self {
+        TraceError::BindGroup(error)
+    }
+}
+
+impl From<wgc::pipeline::ComputePipelineError> for TraceError {
+    fn from(error: wgc::pipeline::ComputePipelineError) -> Self {
+        TraceError::ComputePipeline(error)
+    }
+}
+
+impl From<wgc::pipeline::RenderPipelineError> for TraceError {
+    fn from(error: wgc::pipeline::RenderPipelineError) -> Self {
+        TraceError::RenderPipeline(error)
+    }
+}
+
+impl From<wgc::command::TransferError> for TraceError {
+    fn from(error: wgc::command::TransferError) -> Self {
+        TraceError::Transfer(error)
+    }
+}
+
+/// Parse the contents of a trace file, checking the format version first.
+pub fn parse_trace(text: &str) -> Result<Vec<trace::Action>, TraceError> {
+    let (version, actions) = trace::split_header(text);
+    if !trace::is_compatible(version) {
+        return Err(TraceError::IncompatibleVersion(version));
+    }
+    ron::de::from_str(actions).map_err(TraceError::Parse)
+}
+
+/// Load the actions of the trace recorded in `dir`.
+pub fn load_trace(dir: &Path) -> Result<Vec<trace::Action>, TraceError> {
+    let text = fs::read_to_string(dir.join(trace::FILE_NAME)).map_err(TraceError::Io)?;
+    parse_trace(&text)
+}
+
+struct Label(Option<CString>);
+impl Label {
+    fn new(text: &str) -> Self {
+        Self(if text.is_empty() {
+            None
+        } else {
+            Some(CString::new(text).expect("invalid label"))
+        })
+    }
+
+    fn as_ptr(&self) -> *const std::os::raw::c_char {
+        match self.0 {
+            Some(ref c_string) => c_string.as_ptr(),
+            None => ptr::null(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct IdentityPassThrough<I>(PhantomData<I>);
+
+impl<I: Clone + Debug + wgc::id::TypedId> wgc::hub::IdentityHandler<I> for IdentityPassThrough<I> {
+    type Input = I;
+    fn process(&self, id: I, backend: wgt::Backend) -> I {
+        let (index, epoch, _backend) = id.unzip();
+        I::zip(index, epoch, backend)
+    }
+    fn free(&self, _id: I) {}
+}
+
+#[derive(Debug)]
+pub struct IdentityPassThroughFactory;
+
+impl<I: Clone + Debug + wgc::id::TypedId> wgc::hub::IdentityHandlerFactory<I>
+    for IdentityPassThroughFactory
+{
+    type Filter = IdentityPassThrough<I>;
+    fn spawn(&self, _min_index: u32) -> Self::Filter {
+        IdentityPassThrough(PhantomData)
+    }
+}
+impl wgc::hub::GlobalIdentityHandlerFactory for IdentityPassThroughFactory {}
+
+pub trait GlobalExt {
+    fn encode_commands<B: wgc::hub::GfxBackend>(
+        &self,
+        encoder: wgc::id::CommandEncoderId,
+        commands: Vec<trace::Command>,
+    ) -> Result<wgc::id::CommandBufferId, TraceError>;
+    fn process<B: wgc::hub::GfxBackend>(
+        &self,
+        device: wgc::id::DeviceId,
+        action: trace::Action,
+        dir: &PathBuf,
+        comb_manager: &mut wgc::hub::IdentityManager,
+    ) -> Result<(), TraceError>;
+    fn read_texture<B: wgc::hub::GfxBackend>(
+        &self,
+        device: wgc::id::DeviceId,
+        texture: wgc::id::TextureId,
+        size: wgt::Extent3d,
+        bytes_per_texel: u32,
+        buffer: wgc::id::BufferId,
+        comb_manager: &mut wgc::hub::IdentityManager,
+    ) -> Result<Vec<u8>, TraceError>;
+}
+
+/// Stores the failed status into the `Option<String>` pointed by `user_data`.
+unsafe extern "C" fn map_callback(status: wgc::resource::BufferMapAsyncStatus, user_data: *mut u8) {
+    match status {
+        wgc::resource::BufferMapAsyncStatus::Success => (),
+        _ => *(user_data as *mut Option<String>) = Some(format!("{:?}", status)),
+    }
+}
+
+impl GlobalExt for wgc::hub::Global<IdentityPassThroughFactory> {
+    fn encode_commands<B: wgc::hub::GfxBackend>(
+        &self,
+        encoder: wgc::id::CommandEncoderId,
+        commands: Vec<trace::Command>,
+    ) -> Result<wgc::id::CommandBufferId, TraceError> {
+        for command in commands {
+            match command {
+                trace::Command::CopyBufferToBuffer {
+                    src,
+                    src_offset,
+                    dst,
+                    dst_offset,
+                    size,
+                } => self.command_encoder_copy_buffer_to_buffer::<B>(
+                    encoder, src, src_offset, dst, dst_offset, size,
+                )?,
+                trace::Command::CopyBufferToTexture { src, dst, size } => {
+                    self.command_encoder_copy_buffer_to_texture::<B>(encoder, &src, &dst, &size)?
+                }
+                trace::Command::CopyTextureToBuffer { src, dst, size } => {
+                    self.command_encoder_copy_texture_to_buffer::<B>(encoder, &src, &dst, &size)?
+                }
+                trace::Command::CopyTextureToTexture { src, dst, size } => {
+                    self.command_encoder_copy_texture_to_texture::<B>(encoder, &src, &dst, &size)?
+                }
+                trace::Command::ResolveTexture { src, dst, size } => {
+                    self.command_encoder_resolve_texture::<B>(encoder, &src, &dst, &size)?
+                }
+                trace::Command::RunComputePass { base } => {
+                    self.command_encoder_run_compute_pass_impl::<B>(encoder, base.as_ref());
+                }
+                trace::Command::RunRenderPass {
+                    base,
+                    target_colors,
+                    target_depth_stencil,
+                } => {
+                    self.command_encoder_run_render_pass_impl::<B>(
+                        encoder,
+                        base.as_ref(),
+                        &target_colors,
+                        target_depth_stencil.as_ref(),
+                    );
+                }
+            }
+        }
+        Ok(self.command_encoder_finish::<B>(encoder, &wgt::CommandBufferDescriptor { todo: 0 }))
+    }
+
+    fn process<B: wgc::hub::GfxBackend>(
+        &self,
+        device: wgc::id::DeviceId,
+        action: trace::Action,
+        dir: &PathBuf,
+        comb_manager: &mut wgc::hub::IdentityManager,
+    ) -> Result<(), TraceError> {
+        use wgc::device::trace::Action as A;
+        match action {
+            A::Init { .. } => {
+                return Err(TraceError::UnexpectedAction(
+                    "Action::Init has to be the first action only",
+                ))
+            }
+            A::CreateSwapChain { .. } | A::PresentSwapChain(_) => {
+                return Err(TraceError::UnexpectedAction(
+                    "SwapChain actions need the winit feature",
+                ))
+            }
+            A::CreateBuffer { id, desc } => {
+                let label = Label::new(&desc.label);
+                self.device_maintain_ids::<B>(device);
+                self.device_create_buffer::<B>(device, &desc.map_label(|_| label.as_ptr()), id);
+            }
+            A::DestroyBuffer(id) => {
+                self.buffer_destroy::<B>(id);
+            }
+            A::CreateTexture { id, desc } => {
+                let label = Label::new(&desc.label);
+                self.device_maintain_ids::<B>(device);
+                self.device_create_texture::<B>(device, &desc.map_label(|_| label.as_ptr()), id);
+            }
+            A::DestroyTexture(id) => {
+                self.texture_destroy::<B>(id);
+            }
+            A::CreateTextureView {
+                id,
+                parent_id,
+                desc,
+            } => {
+                let label = desc.as_ref().map_or(Label(None), |d| Label::new(&d.label));
+                self.device_maintain_ids::<B>(device);
+                self.texture_create_view::<B>(
+                    parent_id,
+                    desc.map(|d| d.map_label(|_| label.as_ptr())).as_ref(),
+                    id,
+                );
+            }
+            A::DestroyTextureView(id) => {
+                self.texture_view_destroy::<B>(id);
+            }
+            A::CreateSampler { id, desc } => {
+                let label = Label::new(&desc.label);
+                self.device_maintain_ids::<B>(device);
+                self.device_create_sampler::<B>(device, &desc.map_label(|_| label.as_ptr()), id);
+            }
+            A::DestroySampler(id) => {
+                self.sampler_destroy::<B>(id);
+            }
+            A::GetSwapChainTexture { id, parent_id } => {
+                if let Some(id) = id {
+                    self.swap_chain_get_next_texture::<B>(parent_id, id)
+                        .view_id
+                        .ok_or(TraceError::NoSwapChainTexture)?;
+                }
+            }
+            A::CreateBindGroupLayout {
+                id,
+                ref label,
+                ref entries,
+            } => {
+                self.device_create_bind_group_layout::<B>(
+                    device,
+                    &wgt::BindGroupLayoutDescriptor {
+                        label: Some(label),
+                        entries,
+                    },
+                    id,
+                )?;
+            }
+            A::DestroyBindGroupLayout(id) => {
+                self.bind_group_layout_destroy::<B>(id);
+            }
+            A::CreatePipelineLayout {
+                id,
+                bind_group_layouts,
+                push_constant_ranges,
+            } => {
+                self.device_maintain_ids::<B>(device);
+                self.device_create_pipeline_layout::<B>(
+                    device,
+                    &wgt::PipelineLayoutDescriptor {
+                        bind_group_layouts: &bind_group_layouts,
+                        push_constant_ranges: &push_constant_ranges,
+                    },
+                    id,
+                )?;
+            }
+            A::DestroyPipelineLayout(id) => {
+                self.pipeline_layout_destroy::<B>(id);
+            }
+            A::CreateBindGroup {
+                id,
+                label,
+                layout_id,
+                entries,
+            } => {
+                use wgc::binding_model as bm;
+                let entry_vec = entries
+                    .iter()
+                    .map(|(binding, res)| wgc::binding_model::BindGroupEntry {
+                        binding: *binding,
+                        resource: match *res {
+                            trace::BindingResource::Buffer { id, offset, size } => {
+                                bm::BindingResource::Buffer(bm::BufferBinding {
+                                    buffer_id: id,
+                                    offset,
+                                    size,
+                                })
+                            }
+                            trace::BindingResource::Sampler(id) => bm::BindingResource::Sampler(id),
+                            trace::BindingResource::TextureView(id) => {
+                                bm::BindingResource::TextureView(id)
+                            }
+                            trace::BindingResource::TextureViewArray(ref binding_array) => {
+                                bm::BindingResource::TextureViewArray(binding_array)
+                            }
+                        },
+                    })
+                    .collect::<Vec<_>>();
+                self.device_maintain_ids::<B>(device);
+                self.device_create_bind_group::<B>(
+                    device,
+                    &wgc::binding_model::BindGroupDescriptor {
+                        label: Some(&label),
+                        layout: layout_id,
+                        entries: &entry_vec,
+                    },
+                    id,
+                )?;
+            }
+            A::DestroyBindGroup(id) => {
+                self.bind_group_destroy::<B>(id);
+            }
+            A::CreateShaderModule { id, data } => {
+                let byte_vec = fs::read(dir.join(data)).map_err(TraceError::Data)?;
+                let spv = byte_vec
+                    .chunks(4)
+                    .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                    .collect::<Vec<_>>();
+                self.device_create_shader_module::<B>(
+                    device,
+                    wgc::pipeline::ShaderModuleSource::SpirV(&spv),
+                    id,
+                );
+            }
+            A::DestroyShaderModule(id) => {
+                self.shader_module_destroy::<B>(id);
+            }
+            A::CreateComputePipeline { id, desc } => {
+                let compute_stage = desc.compute_stage.to_core();
+                self.device_maintain_ids::<B>(device);
+                self.device_create_compute_pipeline::<B>(
+                    device,
+                    &wgc::pipeline::ComputePipelineDescriptor {
+                        layout: desc.layout,
+                        compute_stage,
+                    },
+                    id,
+                )?;
+            }
+            A::DestroyComputePipeline(id) => {
+                self.compute_pipeline_destroy::<B>(id);
+            }
+            A::CreateRenderPipeline { id, desc } => {
+                let vertex_stage = desc.vertex_stage.to_core();
+                let fragment_stage = desc.fragment_stage.as_ref().map(|fs| fs.to_core());
+                let vertex_buffers = desc
+                    .vertex_state
+                    .vertex_buffers
+                    .iter()
+                    .map(|vb| wgt::VertexBufferDescriptor {
+                        stride: vb.stride,
+                        step_mode: vb.step_mode,
+                        attributes: &vb.attributes,
+                    })
+                    .collect::<Vec<_>>();
+                self.device_maintain_ids::<B>(device);
+                self.device_create_render_pipeline::<B>(
+                    device,
+                    &wgc::pipeline::RenderPipelineDescriptor {
+                        layout: desc.layout,
+                        vertex_stage,
+                        fragment_stage,
+                        primitive_topology: desc.primitive_topology,
+                        rasterization_state: desc.rasterization_state,
+                        color_states: &desc.color_states,
+                        depth_stencil_state: desc.depth_stencil_state,
+                        vertex_state: wgt::VertexStateDescriptor {
+                            index_format: desc.vertex_state.index_format,
+                            vertex_buffers: &vertex_buffers,
+                            primitive_restart: desc.vertex_state.primitive_restart,
+                        },
+                        sample_count: desc.sample_count,
+                        sample_mask: desc.sample_mask,
+                        alpha_to_coverage_enabled: desc.alpha_to_coverage_enabled,
+                    },
+                    id,
+                )?;
+            }
+            A::DestroyRenderPipeline(id) => {
+                self.render_pipeline_destroy::<B>(id);
+            }
+            A::CreateRenderBundle { id, desc, base } => {
+                let label = Label::new(&desc.label);
+                let bundle = wgc::command::RenderBundleEncoder::new(
+                    &wgt::RenderBundleEncoderDescriptor {
+                        label: None,
+                        color_formats: &desc.color_formats,
+                        depth_stencil_format: desc.depth_stencil_format,
+                        sample_count: desc.sample_count,
+                    },
+                    device,
+                    Some(base),
+                );
+                self.render_bundle_encoder_finish::<B>(
+                    bundle,
+                    &wgt::RenderBundleDescriptor {
+                        label: label.as_ptr(),
+                    },
+                    id,
+                );
+            }
+            A::DestroyRenderBundle(id) => {
+                self.render_bundle_destroy::<B>(id);
+            }
+            A::WriteBuffer {
+                id,
+                data,
+                range,
+                queued,
+            } => {
+                let bin = fs::read(dir.join(data)).map_err(TraceError::Data)?;
+                let size = (range.end - range.start) as usize;
+                if queued {
+                    self.queue_write_buffer::<B>(device, id, range.start, &bin);
+                } else {
+                    self.device_wait_for_buffer::<B>(device, id);
+                    self.device_set_buffer_sub_data::<B>(device, id, range.start, &bin[..size]);
+                }
+            }
+            A::WriteTexture {
+                to,
+                data,
+                layout,
+                size,
+            } => {
+                let bin = fs::read(dir.join(data)).map_err(TraceError::Data)?;
+                self.queue_write_texture::<B>(device, &to, &bin, &layout, &size);
+            }
+            A::Submit(_index, commands) => {
+                let encoder = self.device_create_command_encoder::<B>(
+                    device,
+                    &wgt::CommandEncoderDescriptor { label: ptr::null() },
+                    comb_manager.alloc(device.backend()),
+                );
+                let comb = self.encode_commands::<B>(encoder, commands)?;
+                self.queue_submit::<B>(device, &[comb]);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_texture<B: wgc::hub::GfxBackend>(
+        &self,
+        device: wgc::id::DeviceId,
+        texture: wgc::id::TextureId,
+        size: wgt::Extent3d,
+        bytes_per_texel: u32,
+        buffer: wgc::id::BufferId,
+        comb_manager: &mut wgc::hub::IdentityManager,
+    ) -> Result<Vec<u8>, TraceError> {
+        let row_size = size.width * bytes_per_texel;
+        let align = wgt::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let bytes_per_row = (row_size + align - 1) / align * align;
+        let rows = size.height * size.depth;
+        let buffer_size = (bytes_per_row * rows) as wgt::BufferAddress;
+
+        self.device_maintain_ids::<B>(device);
+        self.device_create_buffer::<B>(
+            device,
+            &wgt::BufferDescriptor {
+                label: ptr::null(),
+                size: buffer_size,
+                usage: wgt::BufferUsage::MAP_READ | wgt::BufferUsage::COPY_DST,
+                mapped_at_creation: false,
+            },
+            buffer,
+        );
+        let encoder = self.device_create_command_encoder::<B>(
+            device,
+            &wgt::CommandEncoderDescriptor { label: ptr::null() },
+            comb_manager.alloc(device.backend()),
+        );
+        self.command_encoder_copy_texture_to_buffer::<B>(
+            encoder,
+            &wgc::command::TextureCopyView {
+                texture,
+                mip_level: 0,
+                origin: wgt::Origin3d::ZERO,
+            },
+            &wgc::command::BufferCopyView {
+                buffer,
+                layout: wgt::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row,
+                    rows_per_image: size.height,
+                },
+            },
+            &size,
+        )?;
+        let comb =
+            self.command_encoder_finish::<B>(encoder, &wgt::CommandBufferDescriptor { todo: 0 });
+        self.queue_submit::<B>(device, &[comb]);
+
+        let mut map_failure = None::<String>;
+        self.buffer_map_async::<B>(
+            buffer,
+            0..buffer_size,
+            wgc::resource::BufferMapOperation {
+                host: wgc::device::HostMap::Read,
+                callback: map_callback,
+                user_data: &mut map_failure as *mut Option<String> as *mut u8,
+            },
+        );
+        self.device_poll::<B>(device, true);
+        if let Some(status) = map_failure {
+            self.buffer_destroy::<B>(buffer);
+            return Err(TraceError::MapReadBack(status));
+        }
+        let mapped = self.buffer_get_mapped_range::<B>(buffer, 0, None);
+        let mut data = Vec::with_capacity((row_size * rows) as usize);
+        for row in 0..rows {
+            let row_data = unsafe {
+                std::slice::from_raw_parts(
+                    mapped.add((row * bytes_per_row) as usize),
+                    row_size as usize,
+                )
+            };
+            data.extend_from_slice(row_data);
+        }
+        self.buffer_unmap::<B>(buffer);
+        self.buffer_destroy::<B>(buffer);
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn trace_without_header() {
+        match parse_trace("[\n]") {
+            Err(TraceError::IncompatibleVersion(0)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn trace_from_the_future() {
+        let text = format!("{}{}\n[\n]", trace::HEADER_PREFIX, trace::VERSION + 1);
+        match parse_trace(&text) {
+            Err(TraceError::IncompatibleVersion(version)) => {
+                assert_eq!(version, trace::VERSION + 1)
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn trace_with_header() {
+        let text = format!("{}{}\n[\n]", trace::HEADER_PREFIX, trace::VERSION);
+        assert!(parse_trace(&text).unwrap().is_empty());
+    }
+
+    const SCISSOR_ACTIONS: &str = "[
+    Submit(1, [
+        RunRenderPass(
+            base: (
+                commands: [SetScissor((x: 0, y: 0, w: 4, h: 4))],
+                dynamic_offsets: [],
+                string_data: [],
+                push_constant_data: [],
+            ),
+            target_colors: [],
+            target_depth_stencil: None,
+        ),
+    ]),
+]";
+
+    #[test]
+    fn scissor_without_header() {
+        match parse_trace(SCISSOR_ACTIONS) {
+            Err(TraceError::IncompatibleVersion(0)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn scissor_with_header() {
+        let text = format!(
+            "{}{}\n{}",
+            trace::HEADER_PREFIX,
+            trace::MIN_COMPATIBLE_VERSION,
+            SCISSOR_ACTIONS
+        );
+        match parse_trace(&text).unwrap().pop() {
+            Some(trace::Action::Submit(_, mut commands)) => match commands.pop() {
+                Some(trace::Command::RunRenderPass { base, .. }) => match base.commands[..] {
+                    [wgc::command::RenderCommand::SetScissor(ref rect)] => assert_eq!(rect.w, 4),
+                    ref other => panic!("Unexpected commands {:?}", other),
+                },
+                other => panic!("Unexpected command {:?}", other),
+            },
+            other => panic!("Unexpected action {:?}", other),
+        }
+    }
+}
@@ -3,412 +3,97 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 /*! This is a player for WebGPU traces.
- *
- * # Notes
- * - we call device_maintain_ids() before creating any refcounted resource,
- *   which is basically everything except for BGL and shader modules,
- *   so that we don't accidentally try to use the same ID.
 !*/
 
+use player::{gfx_select, GlobalExt as _, IdentityPassThroughFactory};
 use wgc::device::trace;
 
 use std::{
-    ffi::CString,
+    collections::{BTreeMap, BTreeSet},
     fmt::Debug,
-    fs,
-    marker::PhantomData,
+    io::{self, BufRead as _},
     path::{Path, PathBuf},
-    ptr,
 };
 
-macro_rules! gfx_select {
-    ($id:expr => $global:ident.$method:ident( $($param:expr),+ )) => {
-        match $id.backend() {
-            #[cfg(not(any(target_os = "ios", target_os = "macos")))]
-            wgt::Backend::Vulkan => $global.$method::<wgc::backend::Vulkan>( $($param),+ ),
-            #[cfg(any(target_os = "ios", target_os = "macos"))]
-            wgt::Backend::Metal => $global.$method::<wgc::backend::Metal>( $($param),+ ),
-            #[cfg(windows)]
-            wgt::Backend::Dx12 => $global.$method::<wgc::backend::Dx12>( $($param),+ ),
-            #[cfg(windows)]
-            wgt::Backend::Dx11 => $global.$method::<wgc::backend::Dx11>( $($param),+ ),
-            _ => unreachable!()
-        }
-    };
+/// Resources that are alive at the current point of the replay, grouped by type.
+#[cfg_attr(feature = "winit", allow(dead_code))]
+#[derive(Default)]
+struct ResourceTable {
+    live: BTreeMap<&'static str, BTreeSet<String>>,
 }
 
-struct Label(Option<CString>);
-impl Label {
-    fn new(text: &str) -> Self {
-        Self(if text.is_empty() {
-            None
-        } else {
-            Some(CString::new(text).expect("invalid label"))
-        })
+impl ResourceTable {
+    fn add(&mut self, kind: &'static str, id: impl Debug) {
+        self.live
+            .entry(kind)
+            .or_default()
+            .insert(format!("{:?}", id));
     }
 
-    fn as_ptr(&self) -> *const std::os::raw::c_char {
-        match self.0 {
-            Some(ref c_string) => c_string.as_ptr(),
-            None => ptr::null(),
+    fn remove(&mut self, kind: &'static str, id: impl Debug) {
+        if let Some(set) = self.live.get_mut(kind) {
+            set.remove(&format!("{:?}", id));
         }
     }
-}
-
-#[derive(Debug)]
-struct IdentityPassThrough<I>(PhantomData<I>);
-
-impl<I: Clone + Debug + wgc::id::TypedId> wgc::hub::IdentityHandler<I> for IdentityPassThrough<I> {
-    type Input = I;
-    fn process(&self, id: I, backend: wgt::Backend) -> I {
-        let (index, epoch, _backend) = id.unzip();
-        I::zip(index, epoch, backend)
-    }
-    fn free(&self, _id: I) {}
-}
-
-struct IdentityPassThroughFactory;
 
-impl<I: Clone + Debug + wgc::id::TypedId> wgc::hub::IdentityHandlerFactory<I>
-    for IdentityPassThroughFactory
-{
-    type Filter = IdentityPassThrough<I>;
-    fn spawn(&self, _min_index: u32) -> Self::Filter {
-        IdentityPassThrough(PhantomData)
+    fn register(&mut self, action: &trace::Action) {
+        use wgc::device::trace::Action as A;
+        match *action {
+            A::CreateBuffer { id, .. } => self.add("Buffer", id),
+            A::DestroyBuffer(id) => self.remove("Buffer", id),
+            A::CreateTexture { id, .. } => self.add("Texture", id),
+            A::DestroyTexture(id) => self.remove("Texture", id),
+            A::CreateTextureView { id, .. } => self.add("TextureView", id),
+            A::DestroyTextureView(id) => self.remove("TextureView", id),
+            A::CreateSampler { id, .. } => self.add("Sampler", id),
+            A::DestroySampler(id) => self.remove("Sampler", id),
+            A::CreateBindGroupLayout { id, .. } => self.add("BindGroupLayout", id),
+            A::DestroyBindGroupLayout(id) => self.remove("BindGroupLayout", id),
+            A::CreatePipelineLayout { id, .. } => self.add("PipelineLayout", id),
+            A::DestroyPipelineLayout(id) => self.remove("PipelineLayout", id),
+            A::CreateBindGroup { id, .. } => self.add("BindGroup", id),
+            A::DestroyBindGroup(id) => self.remove("BindGroup", id),
+            A::CreateShaderModule { id, .. } => self.add("ShaderModule", id),
+            A::DestroyShaderModule(id) => self.remove("ShaderModule", id),
+            A::CreateComputePipeline { id, .. } => self.add("ComputePipeline", id),
+            A::DestroyComputePipeline(id) => self.remove("ComputePipeline", id),
+            A::CreateRenderPipeline { id, .. } => self.add("RenderPipeline", id),
+            A::DestroyRenderPipeline(id) => self.remove("RenderPipeline", id),
+            A::CreateRenderBundle { id, .. } => self.add("RenderBundle", id),
+            A::DestroyRenderBundle(id) => self.remove("RenderBundle", id),
+            _ => {}
+        }
     }
-}
-impl wgc::hub::GlobalIdentityHandlerFactory for IdentityPassThroughFactory {}
-
-trait GlobalExt {
-    fn encode_commands<B: wgc::hub::GfxBackend>(
-        &self,
-        encoder: wgc::id::CommandEncoderId,
-        commands: Vec<trace::Command>,
-    ) -> wgc::id::CommandBufferId;
-    fn process<B: wgc::hub::GfxBackend>(
-        &self,
-        device: wgc::id::DeviceId,
-        action: trace::Action,
-        dir: &PathBuf,
-        comb_manager: &mut wgc::hub::IdentityManager,
-    );
-}
 
-impl GlobalExt for wgc::hub::Global<IdentityPassThroughFactory> {
-    fn encode_commands<B: wgc::hub::GfxBackend>(
-        &self,
-        encoder: wgc::id::CommandEncoderId,
-        commands: Vec<trace::Command>,
-    ) -> wgc::id::CommandBufferId {
-        for command in commands {
-            match command {
-                trace::Command::CopyBufferToBuffer {
-                    src,
-                    src_offset,
-                    dst,
-                    dst_offset,
-                    size,
-                } => self
-                    .command_encoder_copy_buffer_to_buffer::<B>(
-                        encoder, src, src_offset, dst, dst_offset, size,
-                    )
-                    .unwrap(),
-                trace::Command::CopyBufferToTexture { src, dst, size } => self
-                    .command_encoder_copy_buffer_to_texture::<B>(encoder, &src, &dst, &size)
-                    .unwrap(),
-                trace::Command::CopyTextureToBuffer { src, dst, size } => self
-                    .command_encoder_copy_texture_to_buffer::<B>(encoder, &src, &dst, &size)
-                    .unwrap(),
-                trace::Command::CopyTextureToTexture { src, dst, size } => self
-                    .command_encoder_copy_texture_to_texture::<B>(encoder, &src, &dst, &size)
-                    .unwrap(),
-                trace::Command::RunComputePass { base } => {
-                    self.command_encoder_run_compute_pass_impl::<B>(encoder, base.as_ref());
-                }
-                trace::Command::RunRenderPass {
-                    base,
-                    target_colors,
-                    target_depth_stencil,
-                } => {
-                    self.command_encoder_run_render_pass_impl::<B>(
-                        encoder,
-                        base.as_ref(),
-                        &target_colors,
-                        target_depth_stencil.as_ref(),
-                    );
-                }
+    fn print(&self) {
+        for (kind, ids) in self.live.iter() {
+            println!("{} ({}):", kind, ids.len());
+            for id in ids {
+                println!("\t{}", id);
             }
         }
-        self.command_encoder_finish::<B>(encoder, &wgt::CommandBufferDescriptor { todo: 0 })
     }
+}
 
-    fn process<B: wgc::hub::GfxBackend>(
-        &self,
-        device: wgc::id::DeviceId,
-        action: trace::Action,
-        dir: &PathBuf,
-        comb_manager: &mut wgc::hub::IdentityManager,
-    ) {
-        use wgc::device::trace::Action as A;
-        match action {
-            A::Init { .. } => panic!("Unexpected Action::Init: has to be the first action only"),
-            A::CreateSwapChain { .. } | A::PresentSwapChain(_) => {
-                panic!("Unexpected SwapChain action: winit feature is not enabled")
-            }
-            A::CreateBuffer { id, desc } => {
-                let label = Label::new(&desc.label);
-                self.device_maintain_ids::<B>(device);
-                self.device_create_buffer::<B>(device, &desc.map_label(|_| label.as_ptr()), id);
-            }
-            A::DestroyBuffer(id) => {
-                self.buffer_destroy::<B>(id);
-            }
-            A::CreateTexture { id, desc } => {
-                let label = Label::new(&desc.label);
-                self.device_maintain_ids::<B>(device);
-                self.device_create_texture::<B>(device, &desc.map_label(|_| label.as_ptr()), id);
-            }
-            A::DestroyTexture(id) => {
-                self.texture_destroy::<B>(id);
-            }
-            A::CreateTextureView {
-                id,
-                parent_id,
-                desc,
-            } => {
-                let label = desc.as_ref().map_or(Label(None), |d| Label::new(&d.label));
-                self.device_maintain_ids::<B>(device);
-                self.texture_create_view::<B>(
-                    parent_id,
-                    desc.map(|d| d.map_label(|_| label.as_ptr())).as_ref(),
-                    id,
-                );
-            }
-            A::DestroyTextureView(id) => {
-                self.texture_view_destroy::<B>(id);
-            }
-            A::CreateSampler { id, desc } => {
-                let label = Label::new(&desc.label);
-                self.device_maintain_ids::<B>(device);
-                self.device_create_sampler::<B>(device, &desc.map_label(|_| label.as_ptr()), id);
-            }
-            A::DestroySampler(id) => {
-                self.sampler_destroy::<B>(id);
-            }
-            A::GetSwapChainTexture { id, parent_id } => {
-                if let Some(id) = id {
-                    self.swap_chain_get_next_texture::<B>(parent_id, id)
-                        .view_id
-                        .unwrap();
-                }
-            }
-            A::CreateBindGroupLayout {
-                id,
-                ref label,
-                ref entries,
-            } => {
-                self.device_create_bind_group_layout::<B>(
-                    device,
-                    &wgt::BindGroupLayoutDescriptor {
-                        label: Some(label),
-                        entries,
-                    },
-                    id,
-                )
-                .unwrap();
-            }
-            A::DestroyBindGroupLayout(id) => {
-                self.bind_group_layout_destroy::<B>(id);
-            }
-            A::CreatePipelineLayout {
-                id,
-                bind_group_layouts,
-                push_constant_ranges,
-            } => {
-                self.device_maintain_ids::<B>(device);
-                self.device_create_pipeline_layout::<B>(
-                    device,
-                    &wgt::PipelineLayoutDescriptor {
-                        bind_group_layouts: &bind_group_layouts,
-                        push_constant_ranges: &push_constant_ranges,
-                    },
-                    id,
-                )
-                .unwrap();
-            }
-            A::DestroyPipelineLayout(id) => {
-                self.pipeline_layout_destroy::<B>(id);
-            }
-            A::CreateBindGroup {
-                id,
-                label,
-                layout_id,
-                entries,
-            } => {
-                use wgc::binding_model as bm;
-                let entry_vec = entries
-                    .iter()
-                    .map(|(binding, res)| wgc::binding_model::BindGroupEntry {
-                        binding: *binding,
-                        resource: match *res {
-                            trace::BindingResource::Buffer { id, offset, size } => {
-                                bm::BindingResource::Buffer(bm::BufferBinding {
-                                    buffer_id: id,
-                                    offset,
-                                    size,
-                                })
-                            }
-                            trace::BindingResource::Sampler(id) => bm::BindingResource::Sampler(id),
-                            trace::BindingResource::TextureView(id) => {
-                                bm::BindingResource::TextureView(id)
-                            }
-                            trace::BindingResource::TextureViewArray(ref binding_array) => {
-                                bm::BindingResource::TextureViewArray(binding_array)
-                            }
-                        },
-                    })
-                    .collect::<Vec<_>>();
-                self.device_maintain_ids::<B>(device);
-                self.device_create_bind_group::<B>(
-                    device,
-                    &wgc::binding_model::BindGroupDescriptor {
-                        label: Some(&label),
-                        layout: layout_id,
-                        entries: &entry_vec,
-                    },
-                    id,
-                )
-                .unwrap();
-            }
-            A::DestroyBindGroup(id) => {
-                self.bind_group_destroy::<B>(id);
-            }
-            A::CreateShaderModule { id, data } => {
-                let byte_vec = fs::read(dir.join(data)).unwrap();
-                let spv = byte_vec
-                    .chunks(4)
-                    .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
-                    .collect::<Vec<_>>();
-                self.device_create_shader_module::<B>(
-                    device,
-                    wgc::pipeline::ShaderModuleSource::SpirV(&spv),
-                    id,
-                );
-            }
-            A::DestroyShaderModule(id) => {
-                self.shader_module_destroy::<B>(id);
-            }
-            A::CreateComputePipeline { id, desc } => {
-                let compute_stage = desc.compute_stage.to_core();
-                self.device_maintain_ids::<B>(device);
-                self.device_create_compute_pipeline::<B>(
-                    device,
-                    &wgc::pipeline::ComputePipelineDescriptor {
-                        layout: desc.layout,
-                        compute_stage,
-                    },
-                    id,
-                )
-                .unwrap();
-            }
-            A::DestroyComputePipeline(id) => {
-                self.compute_pipeline_destroy::<B>(id);
-            }
-            A::CreateRenderPipeline { id, desc } => {
-                let vertex_stage = desc.vertex_stage.to_core();
-                let fragment_stage = desc.fragment_stage.as_ref().map(|fs| fs.to_core());
-                let vertex_buffers = desc
-                    .vertex_state
-                    .vertex_buffers
-                    .iter()
-                    .map(|vb| wgt::VertexBufferDescriptor {
-                        stride: vb.stride,
-                        step_mode: vb.step_mode,
-                        attributes: &vb.attributes,
-                    })
-                    .collect::<Vec<_>>();
-                self.device_maintain_ids::<B>(device);
-                self.device_create_render_pipeline::<B>(
-                    device,
-                    &wgc::pipeline::RenderPipelineDescriptor {
-                        layout: desc.layout,
-                        vertex_stage,
-                        fragment_stage,
-                        primitive_topology: desc.primitive_topology,
-                        rasterization_state: desc.rasterization_state,
-                        color_states: &desc.color_states,
-                        depth_stencil_state: desc.depth_stencil_state,
-                        vertex_state: wgt::VertexStateDescriptor {
-                            index_format: desc.vertex_state.index_format,
-                            vertex_buffers: &vertex_buffers,
-                        },
-                        sample_count: desc.sample_count,
-                        sample_mask: desc.sample_mask,
-                        alpha_to_coverage_enabled: desc.alpha_to_coverage_enabled,
-                    },
-                    id,
-                )
-                .unwrap();
-            }
-            A::DestroyRenderPipeline(id) => {
-                self.render_pipeline_destroy::<B>(id);
-            }
-            A::CreateRenderBundle { id, desc, base } => {
-                let label = Label::new(&desc.label);
-                let bundle = wgc::command::RenderBundleEncoder::new(
-                    &wgt::RenderBundleEncoderDescriptor {
-                        label: None,
-                        color_formats: &desc.color_formats,
-                        depth_stencil_format: desc.depth_stencil_format,
-                        sample_count: desc.sample_count,
-                    },
-                    device,
-                    Some(base),
-                );
-                self.render_bundle_encoder_finish::<B>(
-                    bundle,
-                    &wgt::RenderBundleDescriptor {
-                        label: label.as_ptr(),
-                    },
-                    id,
-                );
-            }
-            A::DestroyRenderBundle(id) => {
-                self.render_bundle_destroy::<B>(id);
-            }
-            A::WriteBuffer {
-                id,
-                data,
-                range,
-                queued,
-            } => {
-                let bin = std::fs::read(dir.join(data)).unwrap();
-                let size = (range.end - range.start) as usize;
-                if queued {
-                    self.queue_write_buffer::<B>(device, id, range.start, &bin);
-                } else {
-                    self.device_wait_for_buffer::<B>(device, id);
-                    self.device_set_buffer_sub_data::<B>(device, id, range.start, &bin[..size]);
-                }
-            }
-            A::WriteTexture {
-                to,
-                data,
-                layout,
-                size,
-            } => {
-                let bin = std::fs::read(dir.join(data)).unwrap();
-                self.queue_write_texture::<B>(device, &to, &bin, &layout, &size);
-            }
-            A::Submit(_index, commands) => {
-                let encoder = self.device_create_command_encoder::<B>(
-                    device,
-                    &wgt::CommandEncoderDescriptor { label: ptr::null() },
-                    comb_manager.alloc(device.backend()),
-                );
-                let comb = self.encode_commands::<B>(encoder, commands);
-                self.queue_submit::<B>(device, &[comb]);
-            }
+/// Wait for the user to decide what to do before the given submission.
+///
+/// Returns `false` if the user wants to run the rest of the trace without stopping.
+#[cfg_attr(feature = "winit", allow(dead_code))]
+fn prompt_step(index: usize, resources: &ResourceTable) -> bool {
+    loop {
+        println!(
+            "Submission {}: [Enter] to execute, 'r' to list live resources, 'c' to continue",
+            index
+        );
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line).unwrap_or(0) == 0 {
+            return false;
+        }
+        match line.trim() {
+            "" => return true,
+            "c" => return false,
+            "r" => resources.print(),
+            other => println!("Unknown command '{}'", other),
         }
     }
 }
@@ -421,7 +106,7 @@ fn main() {
 
     #[cfg(feature = "renderdoc")]
     #[cfg_attr(feature = "winit", allow(unused))]
-    let mut rd = renderdoc::RenderDoc::<renderdoc::V110>::new()
+    let mut capture = wgc::capture::FrameCapture::new()
         .expect("Failed to connect to RenderDoc: are you running without it?");
 
     //TODO: setting for the backend bits
@@ -431,10 +116,11 @@ fn main() {
         Some(arg) if Path::new(&arg).is_dir() => PathBuf::from(arg),
         _ => panic!("Provide the dir path as the parameter"),
     };
+    #[cfg_attr(feature = "winit", allow(unused))]
+    let mut step = std::env::args().skip(2).any(|arg| arg == "--step");
 
     log::info!("Loading trace '{:?}'", dir);
-    let file = fs::File::open(dir.join(trace::FILE_NAME)).unwrap();
-    let mut actions: Vec<trace::Action> = ron::de::from_reader(file).unwrap();
+    let mut actions = player::load_trace(&dir).unwrap_or_else(|e| panic!("{}", e));
     actions.reverse(); // allows us to pop from the top
     log::info!("Found {} actions", actions.len());
 
@@ -469,6 +155,7 @@ fn main() {
                         compatible_surface: Some(surface),
                         #[cfg(not(feature = "winit"))]
                         compatible_surface: None,
+                        force_fallback_adapter: false,
                     },
                     wgc::instance::AdapterInputs::IdSet(
                         &[wgc::id::TypedId::zip(0, 0, backend)],
@@ -493,14 +180,24 @@ fn main() {
     #[cfg(not(feature = "winit"))]
     {
         #[cfg(feature = "renderdoc")]
-        rd.start_frame_capture(ptr::null(), ptr::null());
+        let scope = capture.scope();
 
+        let mut resources = ResourceTable::default();
         while let Some(action) = actions.pop() {
-            gfx_select!(device => global.process(device, action, &dir, &mut command_buffer_id_manager));
+            if let trace::Action::Submit(index, _) = action {
+                if step {
+                    step = prompt_step(index, &resources);
+                }
+            }
+            resources.register(&action);
+            gfx_select!(device => global.process(device, action, &dir, &mut command_buffer_id_manager)).unwrap();
+            if step {
+                gfx_select!(device => global.device_poll(device, true));
+            }
         }
 
         #[cfg(feature = "renderdoc")]
-        rd.end_frame_capture(ptr::null(), ptr::null());
+        drop(scope);
         gfx_select!(device => global.device_poll(device, true));
     }
     #[cfg(feature = "winit")]
@@ -535,7 +232,7 @@ fn main() {
                             break;
                         }
                         Some(action) => {
-                            gfx_select!(device => global.process(device, action, &dir, &mut command_buffer_id_manager));
+                            gfx_select!(device => global.process(device, action, &dir, &mut command_buffer_id_manager)).unwrap();
                         }
                         None => break,
                     }
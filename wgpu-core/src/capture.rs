@@ -0,0 +1,78 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/*! In-application frame capture.
+ *
+ * Talks to the RenderDoc in-app API, if the application has been launched
+ * under RenderDoc (or had its library injected), so that tests can capture
+ * the exact frame that misrenders without user interaction.
+ *
+ * The RenderDoc API isn't thread-safe, so `FrameCapture` is meant to be
+ * created and used on a single thread.
+!*/
+
+use std::ptr;
+
+type RenderDoc = renderdoc::RenderDoc<renderdoc::V110>;
+
+/// Handle to the in-application capture API.
+pub struct FrameCapture {
+    rd: RenderDoc,
+}
+
+impl std::fmt::Debug for FrameCapture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrameCapture").finish()
+    }
+}
+
+impl FrameCapture {
+    /// Connect to the capture API.
+    ///
+    /// Returns `None` if the application isn't running under RenderDoc.
+    pub fn new() -> Option<Self> {
+        match RenderDoc::new() {
+            Ok(rd) => Some(FrameCapture { rd }),
+            Err(err) => {
+                log::info!("Frame capture is not available: {:?}", err);
+                None
+            }
+        }
+    }
+
+    /// Capture the next `n_frames` presented frames.
+    pub fn trigger(&mut self, n_frames: u32) {
+        log::info!("Triggering capture of {} frame(s)", n_frames);
+        match n_frames {
+            0 => {}
+            1 => self.rd.trigger_capture(),
+            n => self.rd.trigger_multi_frame_capture(n),
+        }
+    }
+
+    /// Returns true if a capture is currently in progress.
+    pub fn is_capturing(&self) -> bool {
+        self.rd.is_frame_capturing()
+    }
+
+    /// Capture everything that happens on any device until the returned guard is dropped.
+    ///
+    /// This doesn't depend on presentation, so it works for headless workloads.
+    pub fn scope(&mut self) -> CaptureScope {
+        self.rd.start_frame_capture(ptr::null(), ptr::null());
+        CaptureScope { capture: self }
+    }
+}
+
+/// A capture in progress, started by [`FrameCapture::scope`].
+#[derive(Debug)]
+pub struct CaptureScope<'a> {
+    capture: &'a mut FrameCapture,
+}
+
+impl Drop for CaptureScope<'_> {
+    fn drop(&mut self) {
+        self.capture.rd.end_frame_capture(ptr::null(), ptr::null());
+    }
+}
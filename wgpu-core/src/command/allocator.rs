@@ -112,6 +112,7 @@ impl<B: GfxBackend> CommandAllocator<B> {
             used_swap_chain: None,
             limits,
             private_features,
+            debug_markers: Vec::new(),
             #[cfg(feature = "trace")]
             commands: if enable_tracing {
                 Some(Vec::new())
@@ -37,7 +37,7 @@
 !*/
 
 use crate::{
-    command::{BasePass, RenderCommand},
+    command::{BasePass, RenderCommand, INDIRECT_BUFFER_ALIGNMENT},
     conv,
     device::{AttachmentData, Label, RenderPassContext, MAX_VERTEX_BUFFERS, SHADER_STAGE_COUNT},
     hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Input, Storage, Token},
@@ -264,8 +264,10 @@ impl RenderBundle {
                 RenderCommand::ExecuteBundle(_)
                 | RenderCommand::SetBlendColor(_)
                 | RenderCommand::SetStencilReference(_)
+                | RenderCommand::SetDepthBounds { .. }
                 | RenderCommand::SetViewport { .. }
-                | RenderCommand::SetScissor(_) => unreachable!(),
+                | RenderCommand::SetScissor(_)
+                | RenderCommand::SetScissorIndexed { .. } => unreachable!(),
             }
         }
     }
@@ -788,7 +790,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     }
                     RenderCommand::MultiDrawIndirect {
                         buffer_id,
-                        offset: _,
+                        offset,
                         count: None,
                         indexed: false,
                     } => {
@@ -802,6 +804,20 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                             "An invalid drawIndirect call has been made. The buffer usage is {:?} which does not contain required usage INDIRECT",
                             buffer.usage
                         );
+                        assert_eq!(
+                            offset % INDIRECT_BUFFER_ALIGNMENT,
+                            0,
+                            "drawIndirect offset {} is not aligned to {}",
+                            offset,
+                            INDIRECT_BUFFER_ALIGNMENT
+                        );
+                        assert!(
+                            offset + 16 <= buffer.size,
+                            "drawIndirect uses bytes {}..{} which overruns indirect buffer of size {}",
+                            offset,
+                            offset + 16,
+                            buffer.size
+                        );
 
                         commands.extend(state.flush_vertices());
                         commands.extend(state.flush_binds());
@@ -809,7 +825,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     }
                     RenderCommand::MultiDrawIndirect {
                         buffer_id,
-                        offset: _,
+                        offset,
                         count: None,
                         indexed: true,
                     } => {
@@ -823,6 +839,20 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                             "An invalid drawIndexedIndirect call has been made. The buffer usage is {:?} which does not contain required usage INDIRECT",
                             buffer.usage
                         );
+                        assert_eq!(
+                            offset % INDIRECT_BUFFER_ALIGNMENT,
+                            0,
+                            "drawIndexedIndirect offset {} is not aligned to {}",
+                            offset,
+                            INDIRECT_BUFFER_ALIGNMENT
+                        );
+                        assert!(
+                            offset + 20 <= buffer.size,
+                            "drawIndexedIndirect uses bytes {}..{} which overruns indirect buffer of size {}",
+                            offset,
+                            offset + 20,
+                            buffer.size
+                        );
 
                         commands.extend(state.index.flush());
                         commands.extend(state.flush_vertices());
@@ -837,8 +867,10 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     RenderCommand::ExecuteBundle(_)
                     | RenderCommand::SetBlendColor(_)
                     | RenderCommand::SetStencilReference(_)
+                    | RenderCommand::SetDepthBounds { .. }
                     | RenderCommand::SetViewport { .. }
-                    | RenderCommand::SetScissor(_) => {
+                    | RenderCommand::SetScissor(_)
+                    | RenderCommand::SetScissorIndexed { .. } => {
                         unreachable!("not supported by a render bundle")
                     }
                 }
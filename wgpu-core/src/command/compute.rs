@@ -5,8 +5,9 @@
 use crate::{
     command::{
         bind::{Binder, LayoutChange},
-        BasePass, BasePassRef, CommandBuffer,
+        BasePass, BasePassRef, CommandBuffer, INDIRECT_BUFFER_ALIGNMENT,
     },
+    counters::Counter,
     device::all_buffer_stages,
     hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Token},
     id,
@@ -17,7 +18,11 @@ use crate::{
 use hal::command::CommandBuffer as _;
 use wgt::{BufferAddress, BufferUsage};
 
-use std::{fmt, iter, str};
+use std::{
+    fmt, iter,
+    ops::{Deref, DerefMut},
+    str,
+};
 
 #[doc(hidden)]
 #[derive(Clone, Copy, Debug)]
@@ -71,9 +76,54 @@ impl ComputePass {
         }
     }
 
+    /// Start recording a new pass for `parent_id`, reusing the storage of this one.
+    ///
+    /// This avoids allocating the command lists anew for every pass.
+    pub fn reset(&mut self, parent_id: id::CommandEncoderId) {
+        self.base.clear();
+        self.parent_id = parent_id;
+    }
+
     pub fn parent_id(&self) -> id::CommandEncoderId {
         self.parent_id
     }
+
+    /// Push a debug group, which is popped when the returned guard is dropped.
+    ///
+    /// The commands inside of the group are recorded through the guard.
+    pub fn scoped_debug_group(&mut self, label: &str, color: u32) -> ComputePassDebugGroup<'_> {
+        self.base.string_data.extend_from_slice(label.as_bytes());
+        self.base.commands.push(ComputeCommand::PushDebugGroup {
+            color,
+            len: label.len(),
+        });
+        ComputePassDebugGroup { pass: self }
+    }
+}
+
+/// Debug group of a `ComputePass`, see `ComputePass::scoped_debug_group`.
+#[derive(Debug)]
+pub struct ComputePassDebugGroup<'a> {
+    pass: &'a mut ComputePass,
+}
+
+impl Deref for ComputePassDebugGroup<'_> {
+    type Target = ComputePass;
+    fn deref(&self) -> &ComputePass {
+        self.pass
+    }
+}
+
+impl DerefMut for ComputePassDebugGroup<'_> {
+    fn deref_mut(&mut self) -> &mut ComputePass {
+        self.pass
+    }
+}
+
+impl Drop for ComputePassDebugGroup<'_> {
+    fn drop(&mut self) {
+        self.pass.base.commands.push(ComputeCommand::PopDebugGroup);
+    }
 }
 
 impl fmt::Debug for ComputePass {
@@ -128,8 +178,10 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let hub = B::hub(self);
         let mut token = Token::root();
 
+        let (device_guard, mut token) = hub.devices.read(&mut token);
         let (mut cmb_guard, mut token) = hub.command_buffers.write(&mut token);
         let cmb = &mut cmb_guard[encoder_id];
+        let device = &device_guard[cmb.device_id.value];
         let raw = cmb.raw.last_mut().unwrap();
 
         #[cfg(feature = "trace")]
@@ -190,6 +242,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         &bind_group.used,
                         &*buffer_guard,
                         &*texture_guard,
+                        &device.counters,
                     );
 
                     if let Some((pipeline_layout_id, follow_ups)) = state.binder.provide_entry(
@@ -218,6 +271,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 }
                 ComputeCommand::SetPipeline(pipeline_id) => {
                     state.pipeline = PipelineState::Set;
+                    device.counters.add(Counter::PipelineSwitches, 1);
                     let pipeline = cmb
                         .trackers
                         .compute_pipes
@@ -315,6 +369,10 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         PipelineState::Set,
                         "Dispatch DEBUG: Pipeline is missing"
                     );
+                    if groups.contains(&0) {
+                        log::trace!("Ignoring dispatch of {:?} groups", groups);
+                        continue;
+                    }
                     unsafe {
                         raw.dispatch(groups);
                     }
@@ -332,6 +390,21 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         BufferUse::INDIRECT,
                     );
                     assert!(src_buffer.usage.contains(BufferUsage::INDIRECT));
+                    assert_eq!(
+                        offset % INDIRECT_BUFFER_ALIGNMENT,
+                        0,
+                        "dispatchIndirect offset {} is not aligned to {}",
+                        offset,
+                        INDIRECT_BUFFER_ALIGNMENT
+                    );
+                    let end_offset = offset + 12;
+                    assert!(
+                        end_offset <= src_buffer.size,
+                        "dispatchIndirect uses bytes {}..{} which overruns indirect buffer of size {}",
+                        offset,
+                        end_offset,
+                        src_buffer.size
+                    );
 
                     let barriers = src_pending.map(|pending| pending.into_hal(src_buffer));
 
@@ -351,6 +424,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     unsafe {
                         raw.begin_debug_marker(label, color);
                     }
+                    super::note_debug_marker(&mut cmb.debug_markers, label);
                     base.string_data = &base.string_data[len..];
                 }
                 ComputeCommand::PopDebugGroup => {
@@ -366,6 +440,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 ComputeCommand::InsertDebugMarker { color, len } => {
                     let label = str::from_utf8(&base.string_data[..len]).unwrap();
                     unsafe { raw.insert_debug_marker(label, color) }
+                    super::note_debug_marker(&mut cmb.debug_markers, label);
                     base.string_data = &base.string_data[len..];
                 }
             }
@@ -16,20 +16,34 @@ pub use self::render::*;
 pub use self::transfer::*;
 
 use crate::{
+    counters::{Counter, Counters},
     device::{all_buffer_stages, all_image_stages},
     hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Storage, Token},
     id,
     resource::{Buffer, Texture},
     span,
-    track::TrackerSet,
+    track::{PendingTransition, TextureState, TrackerSet},
     PrivateFeatures, Stored,
 };
 
 use hal::command::CommandBuffer as _;
 
-use std::thread::ThreadId;
+use std::{marker::PhantomData, thread::ThreadId};
 
 const PUSH_CONSTANT_CLEAR_ARRAY: &[u32] = &[0_u32; 64];
+/// Number of the most recent debug markers kept for the device loss diagnostics.
+pub(crate) const MAX_DEBUG_MARKERS: usize = 16;
+
+/// Remember the debug marker, dropping the oldest ones above `MAX_DEBUG_MARKERS`.
+pub(crate) fn note_debug_marker(markers: &mut Vec<String>, label: &str) {
+    if markers.len() == MAX_DEBUG_MARKERS {
+        markers.remove(0);
+    }
+    markers.push(label.to_string());
+}
+
+/// Required alignment of the offsets into indirect and count buffers.
+const INDIRECT_BUFFER_ALIGNMENT: wgt::BufferAddress = 4;
 
 #[derive(Debug)]
 pub struct CommandBuffer<B: hal::Backend> {
@@ -41,6 +55,8 @@ pub struct CommandBuffer<B: hal::Backend> {
     pub(crate) used_swap_chain: Option<(Stored<id::SwapChainId>, B::Framebuffer)>,
     limits: wgt::Limits,
     private_features: PrivateFeatures,
+    /// The most recent debug groups and markers, oldest first.
+    pub(crate) debug_markers: Vec<String>,
     #[cfg(feature = "trace")]
     pub(crate) commands: Option<Vec<crate::device::trace::Command>>,
 }
@@ -52,20 +68,30 @@ impl<B: GfxBackend> CommandBuffer<B> {
         head: &TrackerSet,
         buffer_guard: &Storage<Buffer<B>, id::BufferId>,
         texture_guard: &Storage<Texture<B>, id::TextureId>,
+        counters: &Counters,
     ) {
         use hal::command::CommandBuffer as _;
+        span!(_guard, TRACE, "CommandBuffer::insert_barriers");
 
         debug_assert_eq!(B::VARIANT, base.backend());
         debug_assert_eq!(B::VARIANT, head.backend());
 
-        let buffer_barriers = base.buffers.merge_replace(&head.buffers).map(|pending| {
-            let buf = &buffer_guard[pending.id];
-            pending.into_hal(buf)
-        });
-        let texture_barriers = base.textures.merge_replace(&head.textures).map(|pending| {
-            let tex = &texture_guard[pending.id];
-            pending.into_hal(tex)
-        });
+        let buffer_barriers = base
+            .buffers
+            .merge_replace(&head.buffers)
+            .map(|pending| {
+                let buf = &buffer_guard[pending.id];
+                pending.into_hal(buf)
+            })
+            .collect::<Vec<_>>();
+        // The texture transitions come per mip level, merge them where possible.
+        let mut texture_transitions = Vec::<PendingTransition<TextureState>>::new();
+        for pending in base.textures.merge_replace(&head.textures) {
+            match texture_transitions.last_mut() {
+                Some(last) if last.try_merge(&pending) => {}
+                _ => texture_transitions.push(pending),
+            }
+        }
         base.views.merge_extend(&head.views).unwrap();
         base.bind_groups.merge_extend(&head.bind_groups).unwrap();
         base.samplers.merge_extend(&head.samplers).unwrap();
@@ -75,12 +101,24 @@ impl<B: GfxBackend> CommandBuffer<B> {
         base.render_pipes.merge_extend(&head.render_pipes).unwrap();
         base.bundles.merge_extend(&head.bundles).unwrap();
 
+        let count = buffer_barriers.len() + texture_transitions.len();
+        if count == 0 {
+            return;
+        }
+        counters.add(Counter::Barriers, count);
+
+        //TODO: use split barriers (events) once the transitions are tracked
+        // together with the commands that cause them.
+        let texture_barriers = texture_transitions.into_iter().map(|pending| {
+            let tex = &texture_guard[pending.id];
+            pending.into_hal(tex)
+        });
         let stages = all_buffer_stages() | all_image_stages();
         unsafe {
             raw.pipeline_barrier(
                 stages..stages,
                 hal::memory::Dependencies::empty(),
-                buffer_barriers.chain(texture_barriers),
+                buffer_barriers.into_iter().chain(texture_barriers),
             );
         }
     }
@@ -121,6 +159,14 @@ impl<C: Clone> BasePass<C> {
         }
     }
 
+    /// Remove all the recorded data, keeping the allocated memory for reuse.
+    fn clear(&mut self) {
+        self.commands.clear();
+        self.dynamic_offsets.clear();
+        self.string_data.clear();
+        self.push_constant_data.clear();
+    }
+
     #[cfg(feature = "trace")]
     fn from_ref(base: BasePassRef<C>) -> Self {
         BasePass {
@@ -186,6 +232,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         unsafe {
             cmb_raw.begin_debug_marker(label, 0);
         }
+        note_debug_marker(&mut cmb.debug_markers, label);
     }
 
     pub fn command_encoder_insert_debug_marker<B: GfxBackend>(
@@ -205,6 +252,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         unsafe {
             cmb_raw.insert_debug_marker(label, 0);
         }
+        note_debug_marker(&mut cmb.debug_markers, label);
     }
 
     pub fn command_encoder_pop_debug_group<B: GfxBackend>(&self, encoder_id: id::CommandEncoderId) {
@@ -221,6 +269,34 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             cmb_raw.end_debug_marker();
         }
     }
+
+    /// Push a debug group, which is popped when the returned guard is dropped.
+    pub fn command_encoder_scoped_debug_group<B: GfxBackend>(
+        &self,
+        encoder_id: id::CommandEncoderId,
+        label: &str,
+    ) -> CommandEncoderDebugGroup<'_, G, B> {
+        self.command_encoder_push_debug_group::<B>(encoder_id, label);
+        CommandEncoderDebugGroup {
+            global: self,
+            encoder_id,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Debug group of a command encoder, see `Global::command_encoder_scoped_debug_group`.
+pub struct CommandEncoderDebugGroup<'a, G: GlobalIdentityHandlerFactory, B: GfxBackend> {
+    global: &'a Global<G>,
+    encoder_id: id::CommandEncoderId,
+    _marker: PhantomData<B>,
+}
+
+impl<G: GlobalIdentityHandlerFactory, B: GfxBackend> Drop for CommandEncoderDebugGroup<'_, G, B> {
+    fn drop(&mut self) {
+        self.global
+            .command_encoder_pop_debug_group::<B>(self.encoder_id);
+    }
 }
 
 fn push_constant_clear<PushFn>(offset: u32, size_bytes: u32, mut push_fn: PushFn)
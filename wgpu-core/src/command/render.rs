@@ -5,12 +5,13 @@
 use crate::{
     command::{
         bind::{Binder, LayoutChange},
-        BasePass, BasePassRef,
+        BasePass, BasePassRef, INDIRECT_BUFFER_ALIGNMENT,
     },
     conv,
+    counters::Counter,
     device::{
-        AttachmentData, FramebufferKey, RenderPassContext, RenderPassKey, MAX_COLOR_TARGETS,
-        MAX_VERTEX_BUFFERS,
+        AttachmentData, Device, FramebufferKey, RenderPassContext, RenderPassKey,
+        MAX_COLOR_TARGETS, MAX_VERTEX_BUFFERS,
     },
     hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Token},
     id,
@@ -32,7 +33,13 @@ use serde::Deserialize;
 #[cfg(any(feature = "serial-pass", feature = "trace"))]
 use serde::Serialize;
 
-use std::{borrow::Borrow, collections::hash_map::Entry, fmt, iter, ops::Range, str};
+use std::{
+    borrow::Borrow,
+    collections::hash_map::Entry,
+    fmt, iter,
+    ops::{Deref, DerefMut, Range},
+    str,
+};
 
 /// Operation to perform to the output attachment at the start of a renderpass.
 #[repr(C)]
@@ -53,6 +60,9 @@ pub enum LoadOp {
 #[cfg_attr(any(feature = "serial-pass", feature = "replay"), derive(Deserialize))]
 pub enum StoreOp {
     /// Clear the render target. If you don't care about the contents of the target, this can be faster.
+    ///
+    /// A multisampled attachment with a `resolve_target` is still resolved, only its
+    /// own contents are discarded. This saves writing the samples out to memory.
     Clear = 0,
     /// Store the result of the renderpass.
     Store = 1,
@@ -104,6 +114,32 @@ pub struct DepthStencilAttachmentDescriptor {
     pub stencil: PassChannel<u32>,
 }
 
+fn check_viewport_index<B: hal::Backend>(index: u32, device: &Device<B>, name: &str) {
+    assert!(
+        index == 0 || device.features.contains(wgt::Features::MULTI_VIEWPORT),
+        "The feature MULTI_VIEWPORT must be enabled to use {} with index {}",
+        name,
+        index
+    );
+    assert!(
+        (index as usize) < device.hal_limits.max_viewports,
+        "{} index {} is above the limit of {} viewports",
+        name,
+        index,
+        device.hal_limits.max_viewports
+    );
+}
+
+fn map_scissor_rect(rect: &Rect<u32>) -> hal::pso::Rect {
+    use std::{convert::TryFrom, i16};
+    hal::pso::Rect {
+        x: i16::try_from(rect.x).unwrap_or(0),
+        y: i16::try_from(rect.y).unwrap_or(0),
+        w: i16::try_from(rect.w).unwrap_or(i16::MAX),
+        h: i16::try_from(rect.h).unwrap_or(i16::MAX),
+    }
+}
+
 fn is_depth_stencil_read_only(
     desc: &DepthStencilAttachmentDescriptor,
     aspects: hal::format::Aspects,
@@ -164,13 +200,23 @@ pub enum RenderCommand {
     },
     SetBlendColor(Color),
     SetStencilReference(u32),
+    SetDepthBounds {
+        min: f32,
+        max: f32,
+    },
     SetViewport {
+        #[cfg_attr(any(feature = "serial-pass", feature = "replay"), serde(default))]
+        index: u32,
         rect: Rect<f32>,
         //TODO: use half-float to reduce the size?
         depth_min: f32,
         depth_max: f32,
     },
     SetScissor(Rect<u32>),
+    SetScissorIndexed {
+        index: u32,
+        rect: Rect<u32>,
+    },
     SetPushConstant {
         stages: wgt::ShaderStage,
         offset: u32,
@@ -238,9 +284,59 @@ impl RenderPass {
         }
     }
 
+    /// Start recording a new pass described by `desc` for `parent_id`,
+    /// reusing the storage of this one.
+    ///
+    /// This avoids allocating the command lists anew for every pass.
+    pub fn reset(&mut self, parent_id: id::CommandEncoderId, desc: RenderPassDescriptor) {
+        self.base.clear();
+        self.parent_id = parent_id;
+        self.color_targets.clear();
+        self.color_targets
+            .extend(desc.color_attachments.iter().cloned());
+        self.depth_stencil_target = desc.depth_stencil_attachment.cloned();
+    }
+
     pub fn parent_id(&self) -> id::CommandEncoderId {
         self.parent_id
     }
+
+    /// Push a debug group, which is popped when the returned guard is dropped.
+    ///
+    /// The commands inside of the group are recorded through the guard.
+    pub fn scoped_debug_group(&mut self, label: &str, color: u32) -> RenderPassDebugGroup<'_> {
+        self.base.string_data.extend_from_slice(label.as_bytes());
+        self.base.commands.push(RenderCommand::PushDebugGroup {
+            color,
+            len: label.len(),
+        });
+        RenderPassDebugGroup { pass: self }
+    }
+}
+
+/// Debug group of a `RenderPass`, see `RenderPass::scoped_debug_group`.
+#[derive(Debug)]
+pub struct RenderPassDebugGroup<'a> {
+    pass: &'a mut RenderPass,
+}
+
+impl Deref for RenderPassDebugGroup<'_> {
+    type Target = RenderPass;
+    fn deref(&self) -> &RenderPass {
+        self.pass
+    }
+}
+
+impl DerefMut for RenderPassDebugGroup<'_> {
+    fn deref_mut(&mut self) -> &mut RenderPass {
+        self.pass
+    }
+}
+
+impl Drop for RenderPassDebugGroup<'_> {
+    fn drop(&mut self) {
+        self.pass.base.commands.push(RenderCommand::PopDebugGroup);
+    }
 }
 
 impl fmt::Debug for RenderPass {
@@ -277,6 +373,7 @@ impl OptionalState {
 enum DrawError {
     MissingBlendColor,
     MissingStencilReference,
+    MissingDepthBounds,
     MissingPipeline,
     IncompatibleBindGroup {
         index: u32,
@@ -290,6 +387,7 @@ impl fmt::Debug for DrawError {
         match self {
             DrawError::MissingBlendColor => write!(f, "MissingBlendColor. A blend color is required to be set using RenderPass::set_blend_color."),
             DrawError::MissingStencilReference => write!(f, "MissingStencilReference. A stencil reference is required to be set using RenderPass::set_stencil_reference."),
+            DrawError::MissingDepthBounds => write!(f, "MissingDepthBounds. The depth bounds are required to be set using RenderPass::set_depth_bounds."),
             DrawError::MissingPipeline => write!(f, "MissingPipeline. You must first set the render pipeline using RenderPass::set_pipeline."),
             DrawError::IncompatibleBindGroup { index } => write!(f, "IncompatibleBindGroup. The current render pipeline has a layout which is incompatible with a currently set bind group. They first differ at entry index {}.", index),
         }
@@ -342,21 +440,35 @@ impl VertexBufferState {
 struct VertexState {
     inputs: ArrayVec<[VertexBufferState; MAX_VERTEX_BUFFERS]>,
     vertex_limit: u32,
+    /// Slot of the buffer that constrains `vertex_limit`.
+    vertex_limit_slot: u32,
     instance_limit: u32,
+    /// Slot of the buffer that constrains `instance_limit`.
+    instance_limit_slot: u32,
 }
 
 impl VertexState {
     fn update_limits(&mut self) {
         self.vertex_limit = !0;
         self.instance_limit = !0;
-        for vbs in &self.inputs {
+        for (slot, vbs) in self.inputs.iter().enumerate() {
             if vbs.stride == 0 {
                 continue;
             }
             let limit = (vbs.total_size / vbs.stride) as u32;
             match vbs.rate {
-                InputStepMode::Vertex => self.vertex_limit = self.vertex_limit.min(limit),
-                InputStepMode::Instance => self.instance_limit = self.instance_limit.min(limit),
+                InputStepMode::Vertex => {
+                    if limit < self.vertex_limit {
+                        self.vertex_limit = limit;
+                        self.vertex_limit_slot = slot as u32;
+                    }
+                }
+                InputStepMode::Instance => {
+                    if limit < self.instance_limit {
+                        self.instance_limit = limit;
+                        self.instance_limit_slot = slot as u32;
+                    }
+                }
             }
         }
     }
@@ -364,7 +476,9 @@ impl VertexState {
     fn reset(&mut self) {
         self.inputs.clear();
         self.vertex_limit = 0;
+        self.vertex_limit_slot = 0;
         self.instance_limit = 0;
+        self.instance_limit_slot = 0;
     }
 }
 
@@ -373,6 +487,7 @@ struct State {
     binder: Binder,
     blend_color: OptionalState,
     stencil_reference: OptionalState,
+    depth_bounds: OptionalState,
     pipeline: OptionalState,
     index: IndexState,
     vertex: VertexState,
@@ -398,6 +513,9 @@ impl State {
         if self.stencil_reference == OptionalState::Required {
             return Err(DrawError::MissingStencilReference);
         }
+        if self.depth_bounds == OptionalState::Required {
+            return Err(DrawError::MissingDepthBounds);
+        }
         Ok(())
     }
 
@@ -588,6 +706,16 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                                 .textures
                                 .query(source_id.value, view.range.clone());
                             let new_use = TextureUse::ATTACHMENT_WRITE;
+                            let texture = &texture_guard[source_id.value];
+                            if texture.usage.contains(wgt::TextureUsage::TRANSIENT)
+                                && (at.channel.load_op, at.channel.store_op)
+                                    != (LoadOp::Clear, StoreOp::Clear)
+                            {
+                                log::warn!(
+                                    "Transient attachment {:?} is loaded or stored, its contents are undefined outside of the pass",
+                                    at.attachment
+                                );
+                            }
                             output_attachments.push(OutputAttachment {
                                 texture_id: source_id,
                                 range: &view.range,
@@ -775,9 +903,33 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     };
                     let all = entry.key().all().map(|(at, _)| at);
 
-                    let pass =
-                        unsafe { device.raw.create_render_pass(all, iter::once(subpass), &[]) }
-                            .unwrap();
+                    // A read-only depth/stencil attachment can also be sampled by the
+                    // shaders, which have to see the writes done before the pass.
+                    let mut dependencies = ArrayVec::<[hal::pass::SubpassDependency; 1]>::new();
+                    if is_ds_read_only {
+                        use hal::{image::Access as A, pso::PipelineStage as Ps};
+                        dependencies.push(hal::pass::SubpassDependency {
+                            passes: None..Some(0),
+                            stages: Ps::LATE_FRAGMENT_TESTS
+                                ..Ps::VERTEX_SHADER
+                                    | Ps::FRAGMENT_SHADER
+                                    | Ps::EARLY_FRAGMENT_TESTS,
+                            accesses: A::DEPTH_STENCIL_ATTACHMENT_WRITE
+                                ..A::SHADER_READ | A::DEPTH_STENCIL_ATTACHMENT_READ,
+                            flags: hal::memory::Dependencies::empty(),
+                        });
+                    }
+
+                    let mut pass = unsafe {
+                        device
+                            .raw
+                            .create_render_pass(all, iter::once(subpass), dependencies)
+                    }
+                    .unwrap();
+                    let name = device.object_name(None, "RenderPass");
+                    unsafe {
+                        device.raw.set_render_pass_name(&mut pass, &name);
+                    }
                     entry.insert(pass)
                 }
             };
@@ -933,6 +1085,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             binder: Binder::new(cmb.limits.max_bind_groups),
             blend_color: OptionalState::Unused,
             stencil_reference: OptionalState::Unused,
+            depth_bounds: OptionalState::Unused,
             pipeline: OptionalState::Required,
             index: IndexState::default(),
             vertex: VertexState::default(),
@@ -990,6 +1143,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 }
                 RenderCommand::SetPipeline(pipeline_id) => {
                     state.pipeline = OptionalState::Set;
+                    device.counters.add(Counter::PipelineSwitches, 1);
                     let pipeline = trackers
                         .render_pipes
                         .use_extend(&*pipeline_guard, pipeline_id, (), ())
@@ -1011,6 +1165,9 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     state
                         .stencil_reference
                         .require(pipeline.flags.contains(PipelineFlags::STENCIL_REFERENCE));
+                    state
+                        .depth_bounds
+                        .require(pipeline.flags.contains(PipelineFlags::DEPTH_BOUNDS));
 
                     unsafe {
                         raw.bind_graphics_pipeline(&pipeline.raw);
@@ -1098,7 +1255,19 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                             }
                         }
                     }
-                    // Update vertex buffer limits
+                    // Update vertex buffer limits. With the portability checks, the slots
+                    // that aren't bound yet are considered empty, so that drawing without
+                    // them fails.
+                    if cmb.private_features.portability_checks {
+                        let empty_slots = pipeline
+                            .vertex_strides
+                            .len()
+                            .saturating_sub(state.vertex.inputs.len());
+                        state
+                            .vertex
+                            .inputs
+                            .extend(iter::repeat(VertexBufferState::EMPTY).take(empty_slots));
+                    }
                     for (vbs, &(stride, rate)) in
                         state.vertex.inputs.iter_mut().zip(&pipeline.vertex_strides)
                     {
@@ -1185,12 +1354,30 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         raw.set_stencil_reference(hal::pso::Face::all(), value);
                     }
                 }
+                RenderCommand::SetDepthBounds { min, max } => {
+                    assert!(
+                        device.features.contains(wgt::Features::DEPTH_BOUNDS_TEST),
+                        "The feature DEPTH_BOUNDS_TEST must be enabled to use setDepthBounds"
+                    );
+                    assert!(
+                        0.0 <= min && min <= max && max <= 1.0,
+                        "Depth bounds {}..{} must be within 0..1 and not inverted",
+                        min,
+                        max
+                    );
+                    state.depth_bounds = OptionalState::Set;
+                    unsafe {
+                        raw.set_depth_bounds(min..max);
+                    }
+                }
                 RenderCommand::SetViewport {
+                    index,
                     ref rect,
                     depth_min,
                     depth_max,
                 } => {
                     use std::{convert::TryFrom, i16};
+                    check_viewport_index(index, device, "setViewport");
                     let r = hal::pso::Rect {
                         x: i16::try_from(rect.x.round() as i64).unwrap_or(0),
                         y: i16::try_from(rect.y.round() as i64).unwrap_or(0),
@@ -1199,7 +1386,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     };
                     unsafe {
                         raw.set_viewports(
-                            0,
+                            index,
                             iter::once(hal::pso::Viewport {
                                 rect: r,
                                 depth: depth_min..depth_max,
@@ -1239,16 +1426,13 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         )
                     }
                 }
-                RenderCommand::SetScissor(ref rect) => {
-                    use std::{convert::TryFrom, i16};
-                    let r = hal::pso::Rect {
-                        x: i16::try_from(rect.x).unwrap_or(0),
-                        y: i16::try_from(rect.y).unwrap_or(0),
-                        w: i16::try_from(rect.w).unwrap_or(i16::MAX),
-                        h: i16::try_from(rect.h).unwrap_or(i16::MAX),
-                    };
+                RenderCommand::SetScissor(ref rect) => unsafe {
+                    raw.set_scissors(0, iter::once(map_scissor_rect(rect)));
+                },
+                RenderCommand::SetScissorIndexed { index, ref rect } => {
+                    check_viewport_index(index, device, "setScissorRect");
                     unsafe {
-                        raw.set_scissors(0, iter::once(r));
+                        raw.set_scissors(index, iter::once(map_scissor_rect(rect)));
                     }
                 }
                 RenderCommand::Draw {
@@ -1260,16 +1444,26 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     state.is_ready().unwrap();
                     assert!(
                         first_vertex + vertex_count <= state.vertex.vertex_limit,
-                        "Vertex {} extends beyond limit {}",
+                        "Vertex {} extends beyond limit {} of the vertex buffer in slot {}",
                         first_vertex + vertex_count,
-                        state.vertex.vertex_limit
+                        state.vertex.vertex_limit,
+                        state.vertex.vertex_limit_slot
                     );
                     assert!(
                         first_instance + instance_count <= state.vertex.instance_limit,
-                        "Instance {} extends beyond limit {}",
+                        "Instance {} extends beyond limit {} of the vertex buffer in slot {}",
                         first_instance + instance_count,
-                        state.vertex.instance_limit
+                        state.vertex.instance_limit,
+                        state.vertex.instance_limit_slot
                     );
+                    if vertex_count == 0 || instance_count == 0 {
+                        log::trace!(
+                            "Ignoring draw of {} vertices and {} instances",
+                            vertex_count,
+                            instance_count
+                        );
+                        continue;
+                    }
 
                     unsafe {
                         raw.draw(
@@ -1296,10 +1490,19 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     );
                     assert!(
                         first_instance + instance_count <= state.vertex.instance_limit,
-                        "Instance {} extends beyond limit {}",
+                        "Instance {} extends beyond limit {} of the vertex buffer in slot {}",
                         first_instance + instance_count,
-                        state.vertex.instance_limit
+                        state.vertex.instance_limit,
+                        state.vertex.instance_limit_slot
                     );
+                    if index_count == 0 || instance_count == 0 {
+                        log::trace!(
+                            "Ignoring draw of {} indices and {} instances",
+                            index_count,
+                            instance_count
+                        );
+                        continue;
+                    }
 
                     unsafe {
                         raw.draw_indexed(
@@ -1348,6 +1551,15 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         buffer.usage,
                     );
 
+                    assert_eq!(
+                        offset % INDIRECT_BUFFER_ALIGNMENT,
+                        0,
+                        "{} offset {} is not aligned to {}",
+                        name,
+                        offset,
+                        INDIRECT_BUFFER_ALIGNMENT
+                    );
+
                     let actual_count = count.unwrap_or(1);
 
                     let begin_offset = offset;
@@ -1426,6 +1638,25 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                         count_buffer.usage
                     );
 
+                    assert_eq!(
+                        offset % INDIRECT_BUFFER_ALIGNMENT,
+                        0,
+                        "{} offset {} is not aligned to {}",
+                        name,
+                        offset,
+                        INDIRECT_BUFFER_ALIGNMENT
+                    );
+                    assert_eq!(
+                        count_buffer_offset % INDIRECT_BUFFER_ALIGNMENT,
+                        0,
+                        "{} count buffer offset {} is not aligned to {}",
+                        name,
+                        count_buffer_offset,
+                        INDIRECT_BUFFER_ALIGNMENT
+                    );
+
+                    // The number of draws is the minimum of the value in the count buffer
+                    // and `max_count`, so validating the range of `max_count` draws is enough.
                     let begin_offset = offset;
                     let end_offset = offset + stride * max_count as u64;
                     assert!(
@@ -1479,6 +1710,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     unsafe {
                         raw.begin_debug_marker(label, color);
                     }
+                    super::note_debug_marker(&mut cmb.debug_markers, label);
                     base.string_data = &base.string_data[len..];
                 }
                 RenderCommand::PopDebugGroup => {
@@ -1496,6 +1728,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     unsafe {
                         raw.insert_debug_marker(label, color);
                     }
+                    super::note_debug_marker(&mut cmb.debug_markers, label);
                 }
                 RenderCommand::ExecuteBundle(bundle_id) => {
                     let bundle = trackers
@@ -1546,7 +1779,12 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     ot.range.clone(),
                     ot.new_use,
                 )
-                .unwrap();
+                .unwrap_or_else(|pending| {
+                    panic!(
+                        "Attachment {:?} can't be used as {:?} within the pass, only read-only attachments can be bound for reading",
+                        ot.texture_id.value, pending.usage
+                    )
+                });
 
             if let Some(usage) = ot.previous_use {
                 // Make the attachment tracks to be aware of the internal
@@ -1570,6 +1808,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             &trackers,
             &*buffer_guard,
             &*texture_guard,
+            &device.counters,
         );
         unsafe {
             cmb.raw.last_mut().unwrap().finish();
@@ -1668,6 +1907,15 @@ pub mod render_ffi {
             .push(RenderCommand::SetStencilReference(value));
     }
 
+    /// Set the depth bounds, which requires `Features::DEPTH_BOUNDS_TEST`.
+    #[no_mangle]
+    pub extern "C" fn wgpu_render_pass_set_depth_bounds(pass: &mut RenderPass, min: f32, max: f32) {
+        span!(_guard, DEBUG, "RenderPass::set_depth_bounds");
+        pass.base
+            .commands
+            .push(RenderCommand::SetDepthBounds { min, max });
+    }
+
     #[no_mangle]
     pub extern "C" fn wgpu_render_pass_set_viewport(
         pass: &mut RenderPass,
@@ -1680,6 +1928,28 @@ pub mod render_ffi {
     ) {
         span!(_guard, DEBUG, "RenderPass::set_viewport");
         pass.base.commands.push(RenderCommand::SetViewport {
+            index: 0,
+            rect: Rect { x, y, w, h },
+            depth_min,
+            depth_max,
+        });
+    }
+
+    /// Set the viewport at `index`, which requires `Features::MULTI_VIEWPORT` if non-zero.
+    #[no_mangle]
+    pub extern "C" fn wgpu_render_pass_set_viewport_indexed(
+        pass: &mut RenderPass,
+        index: u32,
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+        depth_min: f32,
+        depth_max: f32,
+    ) {
+        span!(_guard, DEBUG, "RenderPass::set_viewport_indexed");
+        pass.base.commands.push(RenderCommand::SetViewport {
+            index,
             rect: Rect { x, y, w, h },
             depth_min,
             depth_max,
@@ -1700,6 +1970,23 @@ pub mod render_ffi {
             .push(RenderCommand::SetScissor(Rect { x, y, w, h }));
     }
 
+    /// Set the scissor rectangle at `index`, which requires `Features::MULTI_VIEWPORT` if non-zero.
+    #[no_mangle]
+    pub extern "C" fn wgpu_render_pass_set_scissor_rect_indexed(
+        pass: &mut RenderPass,
+        index: u32,
+        x: u32,
+        y: u32,
+        w: u32,
+        h: u32,
+    ) {
+        span!(_guard, DEBUG, "RenderPass::set_scissor_rect_indexed");
+        pass.base.commands.push(RenderCommand::SetScissorIndexed {
+            index,
+            rect: Rect { x, y, w, h },
+        });
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn wgpu_render_pass_set_push_constants(
         pass: &mut RenderPass,
@@ -58,6 +58,14 @@ pub enum TransferError {
     InvalidRowsPerImage,
     /// The source and destination layers have different aspects.
     MismatchedAspects,
+    /// The resolved textures don't have a color format.
+    ResolveNonColorAspects,
+    /// The source of a resolve is not multisampled.
+    ResolveSourceNotMultisampled,
+    /// The destination of a resolve is multisampled.
+    ResolveDestinationMultisampled,
+    /// The source and destination of a resolve have different formats.
+    MismatchedResolveFormats,
 }
 
 //TODO: we currently access each texture twice for a transfer,
@@ -269,14 +277,27 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         }
         barriers.extend(dst_pending.map(|pending| pending.into_hal(dst_buffer)));
 
-        if size % wgt::COPY_BUFFER_ALIGNMENT != 0 {
-            return Err(TransferError::UnalignedCopySize);
-        }
-        if source_offset % wgt::COPY_BUFFER_ALIGNMENT != 0 {
-            return Err(TransferError::UnalignedBufferOffset);
-        }
-        if destination_offset % wgt::COPY_BUFFER_ALIGNMENT != 0 {
-            return Err(TransferError::UnalignedBufferOffset);
+        let is_aligned = size % wgt::COPY_BUFFER_ALIGNMENT == 0
+            && source_offset % wgt::COPY_BUFFER_ALIGNMENT == 0
+            && destination_offset % wgt::COPY_BUFFER_ALIGNMENT == 0;
+        if !cmb.private_features.unaligned_buffer_copies {
+            if size % wgt::COPY_BUFFER_ALIGNMENT != 0 {
+                return Err(TransferError::UnalignedCopySize);
+            }
+            if source_offset % wgt::COPY_BUFFER_ALIGNMENT != 0 {
+                return Err(TransferError::UnalignedBufferOffset);
+            }
+            if destination_offset % wgt::COPY_BUFFER_ALIGNMENT != 0 {
+                return Err(TransferError::UnalignedBufferOffset);
+            }
+        } else if !is_aligned && cmb.private_features.portability_checks {
+            log::warn!(
+                "Copy of {} bytes from offset {} to offset {} is not aligned to {}, which is not portable",
+                size,
+                source_offset,
+                destination_offset,
+                wgt::COPY_BUFFER_ALIGNMENT
+            );
         }
 
         let source_end_offset = source_offset + size;
@@ -333,7 +354,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             None => (),
         }
 
-        if copy_size.width == 0 || copy_size.height == 0 || copy_size.width == 0 {
+        if copy_size.width == 0 || copy_size.height == 0 || copy_size.depth == 0 {
             log::trace!("Ignoring copy_buffer_to_texture of size 0");
             return Ok(());
         }
@@ -433,7 +454,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             None => (),
         }
 
-        if copy_size.width == 0 || copy_size.height == 0 || copy_size.width == 0 {
+        if copy_size.width == 0 || copy_size.height == 0 || copy_size.depth == 0 {
             log::trace!("Ignoring copy_texture_to_buffer of size 0");
             return Ok(());
         }
@@ -542,7 +563,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             None => (),
         }
 
-        if copy_size.width == 0 || copy_size.height == 0 || copy_size.width == 0 {
+        if copy_size.width == 0 || copy_size.height == 0 || copy_size.depth == 0 {
             log::trace!("Ignoring copy_texture_to_texture of size 0");
             return Ok(());
         }
@@ -596,4 +617,109 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         }
         Ok(())
     }
+
+    /// Resolves a region of a multisampled texture into a single-sampled one,
+    /// outside of any render pass.
+    pub fn command_encoder_resolve_texture<B: GfxBackend>(
+        &self,
+        command_encoder_id: CommandEncoderId,
+        source: &TextureCopyView,
+        destination: &TextureCopyView,
+        copy_size: &Extent3d,
+    ) -> Result {
+        span!(_guard, INFO, "CommandEncoder::resolve_texture");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+
+        let (mut cmb_guard, mut token) = hub.command_buffers.write(&mut token);
+        let cmb = &mut cmb_guard[command_encoder_id];
+        let (_, mut token) = hub.buffers.read(&mut token); // skip token
+        let (texture_guard, _) = hub.textures.read(&mut token);
+        let mut barriers = Vec::new();
+        let (src_layers, src_range, src_offset) = texture_copy_view_to_hal(source, &*texture_guard);
+        let (dst_layers, dst_range, dst_offset) =
+            texture_copy_view_to_hal(destination, &*texture_guard);
+        if src_layers.aspects != dst_layers.aspects {
+            return Err(TransferError::MismatchedAspects);
+        }
+        if src_layers.aspects != hal::format::Aspects::COLOR {
+            return Err(TransferError::ResolveNonColorAspects);
+        }
+        let src_texture = &texture_guard[source.texture];
+        let dst_texture = &texture_guard[destination.texture];
+        if src_texture.kind.num_samples() <= 1 {
+            return Err(TransferError::ResolveSourceNotMultisampled);
+        }
+        if dst_texture.kind.num_samples() != 1 {
+            return Err(TransferError::ResolveDestinationMultisampled);
+        }
+        if src_texture.format != dst_texture.format {
+            return Err(TransferError::MismatchedResolveFormats);
+        }
+
+        #[cfg(feature = "trace")]
+        match cmb.commands {
+            Some(ref mut list) => list.push(TraceCommand::ResolveTexture {
+                src: source.clone(),
+                dst: destination.clone(),
+                size: *copy_size,
+            }),
+            None => (),
+        }
+
+        if copy_size.width == 0 || copy_size.height == 0 || copy_size.depth == 0 {
+            log::trace!("Ignoring resolve_texture of size 0");
+            return Ok(());
+        }
+
+        let (src_texture, src_pending) = cmb.trackers.textures.use_replace(
+            &*texture_guard,
+            source.texture,
+            src_range,
+            TextureUse::COPY_SRC,
+        );
+        if !src_texture.usage.contains(TextureUsage::COPY_SRC) {
+            return Err(TransferError::MissingCopySrcUsageFlag);
+        }
+        barriers.extend(src_pending.map(|pending| pending.into_hal(src_texture)));
+
+        let (dst_texture, dst_pending) = cmb.trackers.textures.use_replace(
+            &*texture_guard,
+            destination.texture,
+            dst_range,
+            TextureUse::COPY_DST,
+        );
+        if !dst_texture.usage.contains(TextureUsage::COPY_DST) {
+            return Err(TransferError::MissingCopyDstUsageFlag);
+        }
+        barriers.extend(dst_pending.map(|pending| pending.into_hal(dst_texture)));
+
+        validate_texture_copy_range(source, src_texture.kind, copy_size)?;
+        validate_texture_copy_range(destination, dst_texture.kind, copy_size)?;
+
+        let region = hal::command::ImageResolve {
+            src_subresource: src_layers,
+            src_offset,
+            dst_subresource: dst_layers,
+            dst_offset,
+            extent: conv::map_extent(copy_size, src_texture.dimension),
+        };
+        let cmb_raw = cmb.raw.last_mut().unwrap();
+        unsafe {
+            cmb_raw.pipeline_barrier(
+                all_image_stages()..hal::pso::PipelineStage::TRANSFER,
+                hal::memory::Dependencies::empty(),
+                barriers,
+            );
+            cmb_raw.resolve_image(
+                &src_texture.raw,
+                hal::image::Layout::TransferSrcOptimal,
+                &dst_texture.raw,
+                hal::image::Layout::TransferDstOptimal,
+                iter::once(region),
+            );
+        }
+        Ok(())
+    }
 }
@@ -73,8 +73,11 @@ pub fn map_texture_usage(
             value |= U::COLOR_ATTACHMENT;
         }
     }
+    if usage.contains(W::TRANSIENT) {
+        value |= U::TRANSIENT_ATTACHMENT;
+    }
     // Note: TextureUsage::Present does not need to be handled explicitly
-    // TODO: HAL Transient Attachment, HAL Input Attachment
+    // TODO: HAL Input Attachment
     value
 }
 
@@ -249,7 +252,7 @@ pub fn map_depth_stencil_state_descriptor(
         } else {
             None
         },
-        depth_bounds: false, // TODO
+        depth_bounds: desc.depth_bounds_test_enabled,
         stencil: if desc.stencil_read_mask != !0
             || desc.stencil_write_mask != !0
             || desc.stencil_front != wgt::StencilStateFaceDescriptor::IGNORE
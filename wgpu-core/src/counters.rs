@@ -0,0 +1,66 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#[cfg(feature = "counters")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Statistics of the work that wgpu-core did on behalf of the user.
+///
+/// The values are accumulated while recording, and collected on each
+/// queue submission.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InternalCounters {
+    /// Number of pipeline barriers inserted for resource transitions.
+    pub barriers: usize,
+    /// Number of descriptor sets allocated for bind groups.
+    pub descriptor_sets: usize,
+    /// Bytes of memory required by the created buffers.
+    pub buffer_memory: usize,
+    /// Bytes of memory required by the created textures.
+    pub texture_memory: usize,
+    /// Number of pipelines bound in passes.
+    pub pipeline_switches: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Counter {
+    Barriers,
+    DescriptorSets,
+    BufferMemory,
+    TextureMemory,
+    PipelineSwitches,
+}
+
+#[cfg(feature = "counters")]
+const COUNTER_COUNT: usize = 5;
+
+/// Thread-safe storage for the counters.
+///
+/// This is a zero-sized no-op unless the "counters" feature is enabled.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    #[cfg(feature = "counters")]
+    values: [AtomicUsize; COUNTER_COUNT],
+}
+
+impl Counters {
+    #[cfg_attr(not(feature = "counters"), allow(unused_variables))]
+    pub(crate) fn add(&self, counter: Counter, value: usize) {
+        #[cfg(feature = "counters")]
+        self.values[counter as usize].fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Return the accumulated values, and reset them.
+    #[cfg(feature = "counters")]
+    pub(crate) fn take(&self) -> InternalCounters {
+        let take = |counter: Counter| self.values[counter as usize].swap(0, Ordering::Relaxed);
+        InternalCounters {
+            barriers: take(Counter::Barriers),
+            descriptor_sets: take(Counter::DescriptorSets),
+            buffer_memory: take(Counter::BufferMemory),
+            texture_memory: take(Counter::TextureMemory),
+            pipeline_switches: take(Counter::PipelineSwitches),
+        }
+    }
+}
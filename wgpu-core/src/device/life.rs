@@ -5,8 +5,9 @@
 #[cfg(feature = "trace")]
 use crate::device::trace;
 use crate::{
+    device::staging::{StagingBuffer, StagingPool},
     hub::{GfxBackend, GlobalIdentityHandlerFactory, Hub, Token},
-    id, resource,
+    id, resource, span,
     track::TrackerSet,
     FastHashMap, RefCount, Stored, SubmissionIndex,
 };
@@ -178,12 +179,29 @@ impl<B: hal::Backend> NonReferencedResources<B> {
     }
 }
 
+/// Information collected when the device is found to be lost.
+///
+/// This is backend-agnostic, so it can be forwarded to crash telemetry as is.
+#[derive(Clone, Debug)]
+pub struct DeviceLossDiagnostics {
+    /// The error reported by the backend.
+    pub reason: String,
+    /// Submissions that were still in flight when the loss was detected,
+    /// oldest first. The first one is the most likely culprit.
+    pub pending_submissions: Vec<SubmissionIndex>,
+    /// The last debug groups and markers recorded into the pending submissions,
+    /// oldest first.
+    pub last_debug_markers: Vec<String>,
+}
+
 #[derive(Debug)]
 struct ActiveSubmission<B: hal::Backend> {
     index: SubmissionIndex,
     fence: B::Fence,
     last_resources: NonReferencedResources<B>,
+    staging_buffers: Vec<StagingBuffer<B>>,
     mapped: Vec<id::BufferId>,
+    debug_markers: Vec<String>,
 }
 
 /// A struct responsible for tracking resource lifetimes.
@@ -213,6 +231,12 @@ pub struct LifetimeTracker<B: hal::Backend> {
     /// actual deletion.
     free_resources: NonReferencedResources<B>,
     ready_to_map: Vec<id::BufferId>,
+    /// Staging buffers of the finished submissions, available for reuse.
+    pub(crate) staging_pool: StagingPool<StagingBuffer<B>>,
+    /// Set once the device is lost, after which no submission is considered done.
+    pub(crate) lost: Option<DeviceLossDiagnostics>,
+    /// Index of the last submission known to be done.
+    last_done: SubmissionIndex,
 }
 
 impl<B: hal::Backend> LifetimeTracker<B> {
@@ -225,6 +249,9 @@ impl<B: hal::Backend> LifetimeTracker<B> {
             active: Vec::new(),
             free_resources: NonReferencedResources::new(),
             ready_to_map: Vec::new(),
+            staging_pool: StagingPool::new(),
+            lost: None,
+            last_done: 0,
         }
     }
 
@@ -234,6 +261,8 @@ impl<B: hal::Backend> LifetimeTracker<B> {
         fence: B::Fence,
         new_suspects: &SuspectedResources,
         temp_buffers: impl Iterator<Item = (B::Buffer, MemoryBlock<B>)>,
+        staging_buffers: impl Iterator<Item = StagingBuffer<B>>,
+        debug_markers: Vec<String>,
     ) {
         let mut last_resources = NonReferencedResources::new();
         last_resources.buffers.extend(temp_buffers);
@@ -248,7 +277,9 @@ impl<B: hal::Backend> LifetimeTracker<B> {
             index,
             fence,
             last_resources,
+            staging_buffers: staging_buffers.collect(),
             mapped: Vec::new(),
+            debug_markers,
         });
     }
 
@@ -259,8 +290,8 @@ impl<B: hal::Backend> LifetimeTracker<B> {
         });
     }
 
-    fn wait_idle(&self, device: &B::Device) {
-        if !self.active.is_empty() {
+    fn wait_idle(&mut self, device: &B::Device) {
+        if !self.active.is_empty() && self.lost.is_none() {
             log::debug!("Waiting for IDLE...");
             let status = unsafe {
                 device.wait_for_fences(
@@ -270,38 +301,113 @@ impl<B: hal::Backend> LifetimeTracker<B> {
                 )
             };
             log::debug!("...Done");
-            assert_eq!(status, Ok(true), "GPU got stuck :(");
+            match status {
+                Ok(true) => {}
+                // The submissions may still finish, so their resources have to stay alive.
+                Ok(false) => log::error!("GPU got stuck for more than {} ms", CLEANUP_WAIT_MS),
+                Err(err) => self.mark_lost(format!("{:?}", err)),
+            }
+        }
+    }
+
+    fn mark_lost(&mut self, reason: String) {
+        let mut last_debug_markers = self
+            .active
+            .iter()
+            .flat_map(|a| a.debug_markers.iter().cloned())
+            .collect::<Vec<_>>();
+        let skip = last_debug_markers
+            .len()
+            .saturating_sub(crate::command::MAX_DEBUG_MARKERS);
+        last_debug_markers.drain(..skip);
+        let diagnostics = DeviceLossDiagnostics {
+            reason,
+            pending_submissions: self.active.iter().map(|a| a.index).collect(),
+            last_debug_markers,
+        };
+        log::error!("Device is lost: {:?}", diagnostics);
+        self.lost = Some(diagnostics);
+    }
+
+    /// Retires the first `count` active submissions, making their resources
+    /// available for cleanup.
+    fn retire_submissions(&mut self, device: &B::Device, count: usize) {
+        for a in self.active.drain(..count) {
+            log::trace!("Active submission {} is retired", a.index);
+            self.free_resources.extend(a.last_resources);
+            for buffer in a.staging_buffers {
+                self.staging_pool.release(buffer);
+            }
+            self.ready_to_map.extend(a.mapped);
+            unsafe {
+                device.destroy_fence(a.fence);
+            }
+        }
+    }
+
+    /// Waits up to `timeout_ns` for all the active submissions to finish.
+    ///
+    /// Returns `true` if nothing is left in flight.
+    pub(crate) fn wait_for_active(&self, device: &B::Device, timeout_ns: u64) -> bool {
+        if self.active.is_empty() {
+            return true;
+        }
+        let status = unsafe {
+            device.wait_for_fences(
+                self.active.iter().map(|a| &a.fence),
+                hal::device::WaitFor::All,
+                timeout_ns,
+            )
+        };
+        match status {
+            Ok(done) => done,
+            Err(err) => {
+                log::warn!("Waiting for the active submissions failed: {:?}", err);
+                false
+            }
         }
     }
 
     /// Returns the last submission index that is done.
     pub fn triage_submissions(&mut self, device: &B::Device, force_wait: bool) -> SubmissionIndex {
+        span!(_guard, DEBUG, "LifetimeTracker::triage_submissions");
+
         if force_wait {
             self.wait_idle(device);
         }
+        if self.lost.is_some() {
+            // Nothing is going to finish on a lost device, so there is
+            // no point in keeping the resources of the submissions around.
+            let count = self.active.len();
+            self.retire_submissions(device, count);
+            return self.last_done;
+        }
         //TODO: enable when `is_sorted_by_key` is stable
         //debug_assert!(self.active.is_sorted_by_key(|a| a.index));
+        let mut error = None;
         let done_count = self
             .active
             .iter()
-            .position(|a| unsafe { !device.get_fence_status(&a.fence).unwrap() })
+            .position(|a| match unsafe { device.get_fence_status(&a.fence) } {
+                Ok(done) => !done,
+                Err(err) => {
+                    error = Some(err);
+                    true
+                }
+            })
             .unwrap_or_else(|| self.active.len());
-        let last_done = if done_count != 0 {
-            self.active[done_count - 1].index
-        } else {
-            return 0;
-        };
-
-        for a in self.active.drain(..done_count) {
-            log::trace!("Active submission {} is done", a.index);
-            self.free_resources.extend(a.last_resources);
-            self.ready_to_map.extend(a.mapped);
-            unsafe {
-                device.destroy_fence(a.fence);
-            }
+        if let Some(err) = error {
+            self.mark_lost(format!("{:?}", err));
+            let count = self.active.len();
+            self.retire_submissions(device, count);
+            return self.last_done;
+        }
+        if done_count != 0 {
+            self.last_done = self.active[done_count - 1].index;
+            self.retire_submissions(device, done_count);
         }
 
-        last_done
+        self.last_done
     }
 
     pub fn cleanup(
@@ -315,6 +421,7 @@ impl<B: hal::Backend> LifetimeTracker<B> {
                 .clean(device, heaps_mutex, descriptor_allocator_mutex);
             descriptor_allocator_mutex.lock().cleanup(device);
         }
+        self.staging_pool.trim(device, heaps_mutex);
     }
 }
 
@@ -326,6 +433,8 @@ impl<B: GfxBackend> LifetimeTracker<B> {
         #[cfg(feature = "trace")] trace: Option<&Mutex<trace::Trace>>,
         token: &mut Token<super::Device<B>>,
     ) {
+        span!(_guard, DEBUG, "LifetimeTracker::triage_suspected");
+
         if !self.suspected_resources.render_bundles.is_empty() {
             let mut trackers = trackers.lock();
             let (mut guard, _) = hub.render_bundles.write(token);
@@ -550,6 +659,8 @@ impl<B: GfxBackend> LifetimeTracker<B> {
         hub: &Hub<B, G>,
         token: &mut Token<super::Device<B>>,
     ) {
+        span!(_guard, DEBUG, "LifetimeTracker::triage_mapped");
+
         if self.mapped.is_empty() {
             return;
         }
@@ -650,6 +761,8 @@ impl<B: GfxBackend> LifetimeTracker<B> {
         trackers: &Mutex<TrackerSet>,
         token: &mut Token<super::Device<B>>,
     ) -> Vec<super::BufferMapPendingCallback> {
+        span!(_guard, DEBUG, "LifetimeTracker::handle_mapping");
+
         if self.ready_to_map.is_empty() {
             return Vec::new();
         }
@@ -5,6 +5,7 @@
 use crate::{
     binding_model::{self, BindGroupError},
     command, conv,
+    counters::{Counter, Counters},
     hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Hub, Input, Token},
     id, pipeline, resource, span, swap_chain,
     track::{BufferState, TextureState, TrackerSet},
@@ -25,22 +26,45 @@ use parking_lot::{Mutex, MutexGuard};
 use wgt::{BufferAddress, BufferSize, InputStepMode, TextureDimension, TextureFormat};
 
 use std::{
-    collections::hash_map::Entry, ffi, iter, marker::PhantomData, mem, ops::Range, ptr,
-    sync::atomic::Ordering,
+    borrow::Cow,
+    collections::hash_map::Entry,
+    ffi, iter,
+    marker::PhantomData,
+    mem,
+    ops::Range,
+    ptr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
 };
 
 use spirv_headers::ExecutionModel;
 
 mod life;
 mod queue;
+mod staging;
 #[cfg(any(feature = "trace", feature = "replay"))]
 pub mod trace;
 
+pub use life::DeviceLossDiagnostics;
+pub use queue::{ExternalImage, ExternalImageCopyOptions};
+pub use staging::StagingPoolStats;
+
 use smallvec::SmallVec;
 #[cfg(feature = "trace")]
 use trace::{Action, Trace};
 
 pub type Label = *const std::os::raw::c_char;
+fn label_str<'a>(label: Label) -> Option<Cow<'a, str>> {
+    if label.is_null() {
+        None
+    } else {
+        Some(unsafe { ffi::CStr::from_ptr(label) }.to_string_lossy())
+    }
+}
 #[cfg(feature = "trace")]
 fn own_label(label: &Label) -> String {
     if label.is_null() {
@@ -79,6 +103,59 @@ pub fn all_image_stages() -> hal::pso::PipelineStage {
         | Ps::TRANSFER
 }
 
+/// Returns false for the formats that some of the platforms can't filter,
+/// such as the 32-bit floating point ones on the Web.
+fn is_filterable_everywhere(format: wgt::TextureFormat) -> bool {
+    use wgt::TextureFormat as Tf;
+    match format {
+        Tf::R32Float | Tf::Rg32Float | Tf::Rgba32Float => false,
+        _ => true,
+    }
+}
+
+/// Picks the kind of memory a buffer with the given usage is allocated from.
+fn buffer_memory_usage(
+    usage: wgt::BufferUsage,
+    features: wgt::Features,
+    private_features: PrivateFeatures,
+) -> gfx_memory::MemoryUsage {
+    use gfx_memory::MemoryUsage;
+    use wgt::BufferUsage as Bu;
+
+    // Persistence doesn't affect which memory is suitable for the buffer.
+    let usage = usage - Bu::PERSISTENT_MAP;
+    if !usage.intersects(Bu::MAP_READ | Bu::MAP_WRITE) {
+        if private_features.unified_memory && usage.contains(Bu::COPY_DST) {
+            // allows `Queue::write_buffer` to write into it directly
+            MemoryUsage::Dynamic {
+                sparse_updates: true,
+            }
+        } else {
+            MemoryUsage::Private
+        }
+    } else if (Bu::MAP_WRITE | Bu::COPY_SRC).contains(usage) {
+        MemoryUsage::Staging { read_back: false }
+    } else if (Bu::MAP_READ | Bu::COPY_DST).contains(usage) {
+        MemoryUsage::Staging { read_back: true }
+    } else {
+        let is_native_only = features.contains(wgt::Features::MAPPABLE_PRIMARY_BUFFERS);
+        assert!(
+            is_native_only,
+            "MAP usage can only be combined with the opposite COPY, requested {:?}",
+            usage
+        );
+        if private_features.portability_checks {
+            log::warn!(
+                "Portability: buffer usage {:?} requires MAPPABLE_PRIMARY_BUFFERS, which is not available on the Web",
+                usage
+            );
+        }
+        MemoryUsage::Dynamic {
+            sparse_updates: false,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
@@ -167,6 +244,56 @@ fn unmap_buffer<B: hal::Backend>(raw: &B::Device, buffer: &mut resource::Buffer<
     }
 }
 
+/// Map the whole buffer for its lifetime. Returns the pointer and the mapped
+/// range of the memory if it needs explicit synchronization.
+fn map_buffer_persistent<B: hal::Backend>(
+    raw: &B::Device,
+    buffer: &mut resource::Buffer<B>,
+) -> Result<(ptr::NonNull<u8>, Option<hal::memory::Segment>), hal::device::MapError> {
+    let mapped = buffer.memory.map(raw, hal::memory::Segment::ALL)?;
+    let sync_segment = if mapped.is_coherent() {
+        None
+    } else {
+        let mr = mapped.range();
+        Some(hal::memory::Segment {
+            offset: mr.start,
+            size: Some(mr.end - mr.start),
+        })
+    };
+    Ok((mapped.ptr(), sync_segment))
+}
+
+/// Make the host writes to a `range` of a persistently mapped buffer visible
+/// to the device, or the device writes visible to the host, depending on `kind`.
+///
+/// The memory stays mapped, only the affected part of `sync_segment`
+/// (the mapped range of the memory) is flushed or invalidated.
+fn sync_persistent_range<B: hal::Backend>(
+    raw: &B::Device,
+    memory: &B::Memory,
+    sync_segment: &hal::memory::Segment,
+    range: Range<BufferAddress>,
+    kind: HostMap,
+    non_coherent_atom_size: BufferAddress,
+) -> Result<(), hal::device::OutOfMemory> {
+    let mapped_end = sync_segment.offset + sync_segment.size.unwrap();
+    let atom = non_coherent_atom_size.max(1);
+    // non-coherent ranges have to be aligned to the atom size
+    let start = ((sync_segment.offset + range.start) / atom * atom).max(sync_segment.offset);
+    let end = ((sync_segment.offset + range.end + atom - 1) / atom * atom).min(mapped_end);
+    let segment = hal::memory::Segment {
+        offset: start,
+        size: Some(end - start),
+    };
+    let ranges = iter::once((memory, segment));
+    unsafe {
+        match kind {
+            HostMap::Write => raw.flush_mapped_memory_ranges(ranges),
+            HostMap::Read => raw.invalidate_mapped_memory_ranges(ranges),
+        }
+    }
+}
+
 //Note: this logic is specifically moved out of `handle_mapping()` in order to
 // have nothing locked by the time we execute users callback code.
 fn fire_map_callbacks<I: IntoIterator<Item = BufferMapPendingCallback>>(callbacks: I) {
@@ -196,6 +323,12 @@ pub struct Device<B: hal::Backend> {
     pub(crate) private_features: PrivateFeatures,
     pub(crate) limits: wgt::Limits,
     pub(crate) features: wgt::Features,
+    /// Number of objects given a generated debug name so far.
+    object_count: AtomicUsize,
+    pub(crate) counters: Counters,
+    /// Counters collected at the last submission.
+    #[cfg(feature = "counters")]
+    pub(crate) last_counters: crate::counters::InternalCounters,
     //TODO: move this behind another mutex. This would allow several methods to switch
     // to borrow Device immutably, such as `write_buffer`, `write_texture`, and `buffer_unmap`.
     pending_writes: queue::PendingWrites<B>,
@@ -268,6 +401,10 @@ impl<B: GfxBackend> Device<B> {
             private_features,
             limits: desc.limits.clone(),
             features: desc.features.clone(),
+            object_count: AtomicUsize::new(0),
+            counters: Counters::default(),
+            #[cfg(feature = "counters")]
+            last_counters: Default::default(),
             pending_writes: queue::PendingWrites::new(),
         }
     }
@@ -296,6 +433,8 @@ impl<B: GfxBackend> Device<B> {
         force_wait: bool,
         token: &mut Token<'token, Self>,
     ) -> Vec<BufferMapPendingCallback> {
+        span!(_guard, DEBUG, "Device::maintain");
+
         let mut life_tracker = self.lock_life(token);
 
         life_tracker.triage_suspected(
@@ -318,6 +457,20 @@ impl<B: GfxBackend> Device<B> {
         callbacks
     }
 
+    /// Returns the name to give to a raw object for graphics debuggers,
+    /// generating one if the user didn't provide a label.
+    pub(crate) fn object_name(&self, label: Option<&str>, kind: &str) -> String {
+        match label {
+            Some(label) if !label.is_empty() => label.to_string(),
+            _ => format!(
+                "{} #{} (created at submission {})",
+                kind,
+                self.object_count.fetch_add(1, Ordering::Relaxed),
+                self.active_submission_index
+            ),
+        }
+    }
+
     fn untrack<'this, 'token: 'this, G: GlobalIdentityHandlerFactory>(
         &'this mut self,
         hub: &Hub<B, G>,
@@ -384,6 +537,8 @@ impl<B: GfxBackend> Device<B> {
         desc: &wgt::BufferDescriptor<Label>,
         memory_kind: gfx_memory::Kind,
     ) -> resource::Buffer<B> {
+        span!(_guard, DEBUG, "Device::create_buffer");
+
         debug_assert_eq!(self_id.backend(), B::VARIANT);
         let (mut usage, _memory_properties) = conv::map_buffer_usage(desc.usage);
         if desc.mapped_at_creation && !desc.usage.contains(wgt::BufferUsage::MAP_WRITE) {
@@ -391,40 +546,17 @@ impl<B: GfxBackend> Device<B> {
             usage |= hal::buffer::Usage::TRANSFER_DST;
         }
 
-        let mem_usage = {
-            use gfx_memory::MemoryUsage;
-            use wgt::BufferUsage as Bu;
-
-            //TODO: use linear allocation when we can ensure the freeing is linear
-            if !desc.usage.intersects(Bu::MAP_READ | Bu::MAP_WRITE) {
-                MemoryUsage::Private
-            } else if (Bu::MAP_WRITE | Bu::COPY_SRC).contains(desc.usage) {
-                MemoryUsage::Staging { read_back: false }
-            } else if (Bu::MAP_READ | Bu::COPY_DST).contains(desc.usage) {
-                MemoryUsage::Staging { read_back: true }
-            } else {
-                let is_native_only = self
-                    .features
-                    .contains(wgt::Features::MAPPABLE_PRIMARY_BUFFERS);
-                assert!(
-                    is_native_only,
-                    "MAP usage can only be combined with the opposite COPY, requested {:?}",
-                    desc.usage
-                );
-                MemoryUsage::Dynamic {
-                    sparse_updates: false,
-                }
-            }
-        };
+        //TODO: use linear allocation when we can ensure the freeing is linear
+        let mem_usage = buffer_memory_usage(desc.usage, self.features, self.private_features);
 
         let mut buffer = unsafe { self.raw.create_buffer(desc.size.max(1), usage).unwrap() };
-        if !desc.label.is_null() {
-            unsafe {
-                let label = ffi::CStr::from_ptr(desc.label).to_string_lossy();
-                self.raw.set_buffer_name(&mut buffer, &label)
-            };
+        let name = self.object_name(label_str(desc.label).as_deref(), "Buffer");
+        unsafe {
+            self.raw.set_buffer_name(&mut buffer, &name);
         }
         let requirements = unsafe { self.raw.get_buffer_requirements(&buffer) };
+        self.counters
+            .add(Counter::BufferMemory, requirements.size as usize);
         let memory = self
             .mem_allocator
             .lock()
@@ -458,6 +590,8 @@ impl<B: GfxBackend> Device<B> {
         self_id: id::DeviceId,
         desc: &wgt::TextureDescriptor<Label>,
     ) -> resource::Texture<B> {
+        span!(_guard, DEBUG, "Device::create_texture");
+
         debug_assert_eq!(self_id.backend(), B::VARIANT);
 
         // Ensure `D24Plus` textures cannot be copied
@@ -472,6 +606,13 @@ impl<B: GfxBackend> Device<B> {
             }
             _ => {}
         }
+        if desc.usage.contains(wgt::TextureUsage::TRANSIENT) {
+            assert_eq!(
+                desc.usage,
+                wgt::TextureUsage::TRANSIENT | wgt::TextureUsage::OUTPUT_ATTACHMENT,
+                "TRANSIENT textures can only be used as output attachments"
+            );
+        }
 
         let kind = conv::map_texture_dimension_size(desc.dimension, desc.size, desc.sample_count);
         let format = conv::map_texture_format(desc.format, self.private_features);
@@ -506,13 +647,13 @@ impl<B: GfxBackend> Device<B> {
                     view_capabilities,
                 )
                 .unwrap();
-            if !desc.label.is_null() {
-                let label = ffi::CStr::from_ptr(desc.label).to_string_lossy();
-                self.raw.set_image_name(&mut image, &label);
-            }
+            let name = self.object_name(label_str(desc.label).as_deref(), "Texture");
+            self.raw.set_image_name(&mut image, &name);
             image
         };
         let requirements = unsafe { self.raw.get_image_requirements(&image) };
+        self.counters
+            .add(Counter::TextureMemory, requirements.size as usize);
 
         let memory = self
             .mem_allocator
@@ -520,6 +661,7 @@ impl<B: GfxBackend> Device<B> {
             .allocate(
                 &self.raw,
                 &requirements,
+                //TODO: use lazily allocated memory for TRANSIENT textures
                 gfx_memory::MemoryUsage::Private,
                 gfx_memory::Kind::General,
             )
@@ -611,6 +753,9 @@ impl<B: hal::Backend> Device<B> {
         let mut life_tracker = self.life_tracker.lock();
         life_tracker.triage_submissions(&self.raw, true);
         life_tracker.cleanup(&self.raw, &self.mem_allocator, &self.desc_allocator);
+        life_tracker
+            .staging_pool
+            .clear(&self.raw, &self.mem_allocator);
     }
 
     pub(crate) fn dispose(self) {
@@ -644,6 +789,19 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         device.features
     }
 
+    /// Returns true if `Queue::write_buffer` writes directly into the buffers
+    /// that are not in use by the GPU, without a staging copy.
+    pub fn device_has_unified_memory<B: GfxBackend>(&self, device_id: id::DeviceId) -> bool {
+        span!(_guard, INFO, "Device::has_unified_memory");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (device_guard, _) = hub.devices.read(&mut token);
+        let device = &device_guard[device_id];
+
+        device.private_features.unified_memory
+    }
+
     pub fn device_limits<B: GfxBackend>(&self, device_id: id::DeviceId) -> wgt::Limits {
         span!(_guard, INFO, "Device::limits");
 
@@ -681,7 +839,33 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let mut buffer = device.create_buffer(device_id, desc, gfx_memory::Kind::General);
         let ref_count = buffer.life_guard.add_ref();
 
-        let buffer_use = if !desc.mapped_at_creation {
+        let buffer_use = if desc.usage.contains(wgt::BufferUsage::PERSISTENT_MAP) {
+            assert!(
+                device.features.contains(wgt::Features::PERSISTENT_MAPPING),
+                "Feature PERSISTENT_MAPPING must be enabled to use PERSISTENT_MAP buffers"
+            );
+            let host = if desc.usage.contains(wgt::BufferUsage::MAP_WRITE) {
+                HostMap::Write
+            } else if desc.usage.contains(wgt::BufferUsage::MAP_READ) {
+                HostMap::Read
+            } else {
+                panic!("PERSISTENT_MAP usage has to be combined with MAP_READ or MAP_WRITE")
+            };
+            match map_buffer_persistent(&device.raw, &mut buffer) {
+                Ok((ptr, sync_segment)) => {
+                    // the user synchronizes the mapped ranges explicitly
+                    buffer.map_state = resource::BufferMapState::Persistent {
+                        ptr,
+                        host,
+                        sync_segment,
+                    };
+                }
+                Err(e) => {
+                    log::error!("failed to map buffer persistently: {:?}", e);
+                }
+            };
+            resource::BufferUse::EMPTY
+        } else if !desc.mapped_at_creation {
             resource::BufferUse::EMPTY
         } else if desc.usage.contains(wgt::BufferUsage::MAP_WRITE) {
             // buffer is mappable, so we are just doing that at start
@@ -996,6 +1180,13 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     levels: desc.base_mip_level as u8..end_level,
                     layers: desc.base_array_layer as u16..end_layer,
                 };
+                if desc.format != texture.format && device.private_features.portability_checks {
+                    log::warn!(
+                        "Portability: view format {:?} differs from the texture format {:?}",
+                        desc.format,
+                        texture.format
+                    );
+                }
                 (desc.format, kind, range)
             }
             None => {
@@ -1267,11 +1458,10 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 .raw
                 .create_descriptor_set_layout(&raw_bindings, &[])
                 .unwrap();
-            if let Some(label) = desc.label {
-                device
-                    .raw
-                    .set_descriptor_set_layout_name(&mut raw_layout, label);
-            }
+            let name = device.object_name(desc.label, "BindGroupLayout");
+            device
+                .raw
+                .set_descriptor_set_layout_name(&mut raw_layout, &name);
             raw_layout
         };
 
@@ -1558,13 +1748,14 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             desc_sets.pop().unwrap()
         };
 
+        device.counters.add(Counter::DescriptorSets, 1);
+
         // Set the descriptor set's label for easier debugging.
-        if let Some(label) = desc.label {
-            unsafe {
-                device
-                    .raw
-                    .set_descriptor_set_name(desc_set.raw_mut(), &label);
-            }
+        let name = device.object_name(desc.label, "BindGroup");
+        unsafe {
+            device
+                .raw
+                .set_descriptor_set_name(desc_set.raw_mut(), &name);
         }
 
         // Rebind `desc_set` as immutable
@@ -1716,10 +1907,18 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                             .use_extend(&*texture_view_guard, id, (), ())
                             .unwrap();
                         let (pub_usage, internal_use) = match decl.ty {
-                            wgt::BindingType::SampledTexture { .. } => (
-                                wgt::TextureUsage::SAMPLED,
-                                resource::TextureUse::SAMPLED,
-                            ),
+                            wgt::BindingType::SampledTexture { .. } => {
+                                if device.private_features.portability_checks
+                                    && !is_filterable_everywhere(view.format)
+                                {
+                                    log::warn!(
+                                        "Portability: texture format {:?} at binding {} may not support filtering on all platforms",
+                                        view.format,
+                                        binding
+                                    );
+                                }
+                                (wgt::TextureUsage::SAMPLED, resource::TextureUse::SAMPLED)
+                            }
                             wgt::BindingType::StorageTexture { readonly, .. } => (
                                 wgt::TextureUsage::STORAGE,
                                 if readonly {
@@ -2004,6 +2203,12 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             }
         };
 
+        if let Some(ref module) = naga {
+            if let Err(error) = validation::check_module(module, device.features) {
+                panic!("Shader module is not supported by the device: {:?}", error);
+            }
+        }
+
         let shader = pipeline::ShaderModule {
             raw: unsafe { device.raw.create_shader_module(spv).unwrap() },
             device_id: Stored {
@@ -2081,12 +2286,10 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
         unsafe {
             let raw_command_buffer = command_buffer.raw.last_mut().unwrap();
-            if !desc.label.is_null() {
-                let label = ffi::CStr::from_ptr(desc.label).to_string_lossy();
-                device
-                    .raw
-                    .set_command_buffer_name(raw_command_buffer, &label);
-            }
+            let name = device.object_name(label_str(desc.label).as_deref(), "CommandEncoder");
+            device
+                .raw
+                .set_command_buffer_name(raw_command_buffer, &name);
             raw_command_buffer.begin_primary(hal::command::CommandBufferFlags::ONE_TIME_SUBMIT);
         }
 
@@ -2220,10 +2423,28 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             }
         }
 
+        let is_strip = match desc.primitive_topology {
+            wgt::PrimitiveTopology::LineStrip | wgt::PrimitiveTopology::TriangleStrip => true,
+            _ => false,
+        };
+        if desc.vertex_state.primitive_restart && !is_strip {
+            return Err(pipeline::RenderPipelineError::PrimitiveRestartWithList(
+                desc.primitive_topology,
+            ));
+        }
+        if is_strip && !desc.vertex_state.primitive_restart && B::VARIANT == wgt::Backend::Metal {
+            log::warn!(
+                "Primitive restart can't be disabled on Metal, the strips are always restarted"
+            );
+        }
         let input_assembler = hal::pso::InputAssemblerDesc {
             primitive: conv::map_primitive_topology(desc.primitive_topology),
             with_adjacency: false,
-            restart_index: None, //TODO
+            restart_index: if desc.vertex_state.primitive_restart {
+                Some(conv::map_index_format(desc.vertex_state.index_format))
+            } else {
+                None
+            },
         };
 
         let blender = hal::pso::BlendDesc {
@@ -2259,6 +2480,12 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
         let (device_guard, mut token) = hub.devices.read(&mut token);
         let device = &device_guard[device_id];
+        if depth_stencil.depth_bounds && !device.features.contains(wgt::Features::DEPTH_BOUNDS_TEST)
+        {
+            return Err(pipeline::RenderPipelineError::MissingFeature(
+                wgt::Features::DEPTH_BOUNDS_TEST,
+            ));
+        }
         let (raw_pipeline, layout_ref_count) = {
             let (pipeline_layout_guard, mut token) = hub.pipeline_layouts.read(&mut token);
             let (bgl_guard, mut token) = hub.bind_group_layouts.read(&mut token);
@@ -2453,6 +2680,9 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             if ds.is_read_only() {
                 flags |= pipeline::PipelineFlags::DEPTH_STENCIL_READ_ONLY;
             }
+            if ds.depth_bounds_test_enabled {
+                flags |= pipeline::PipelineFlags::DEPTH_BOUNDS;
+            }
         }
 
         let pipeline = pipeline::RenderPipeline {
@@ -2493,6 +2723,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     depth_stencil_state: depth_stencil_state.cloned(),
                     vertex_state: trace::VertexStateDescriptor {
                         index_format: desc.vertex_state.index_format,
+                        primitive_restart: desc.vertex_state.primitive_restart,
                         vertex_buffers: desc_vbs
                             .iter()
                             .map(|vbl| trace::VertexBufferDescriptor {
@@ -2808,6 +3039,75 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         fire_map_callbacks(callbacks);
     }
 
+    /// Waits for the work submitted to the device for at most `timeout`,
+    /// then does the same maintenance as `device_poll`.
+    ///
+    /// Returns `true` if all the submissions have finished in time.
+    pub fn device_poll_timeout<B: GfxBackend>(
+        &self,
+        device_id: id::DeviceId,
+        timeout: Duration,
+    ) -> bool {
+        span!(_guard, INFO, "Device::poll_timeout");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let timeout_ns = timeout.as_nanos().min(u64::max_value() as u128) as u64;
+        let (callbacks, done) = {
+            let (device_guard, mut token) = hub.devices.read(&mut token);
+            let device = &device_guard[device_id];
+            let done = device
+                .lock_life(&mut token)
+                .wait_for_active(&device.raw, timeout_ns);
+            (device.maintain(&hub, false, &mut token), done)
+        };
+        fire_map_callbacks(callbacks);
+        done
+    }
+
+    /// Returns the internal counters collected at the last queue submission.
+    #[cfg(feature = "counters")]
+    pub fn device_get_internal_counters<B: GfxBackend>(
+        &self,
+        device_id: id::DeviceId,
+    ) -> crate::counters::InternalCounters {
+        span!(_guard, INFO, "Device::get_internal_counters");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (device_guard, _) = hub.devices.read(&mut token);
+        device_guard[device_id].last_counters.clone()
+    }
+
+    /// Returns the statistics of the staging buffers used for queue writes.
+    pub fn device_get_staging_pool_stats<B: GfxBackend>(
+        &self,
+        device_id: id::DeviceId,
+    ) -> StagingPoolStats {
+        span!(_guard, INFO, "Device::get_staging_pool_stats");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (device_guard, mut token) = hub.devices.read(&mut token);
+        device_guard[device_id]
+            .lock_life(&mut token)
+            .staging_pool
+            .stats()
+    }
+
+    /// Returns the diagnostics collected when the device got lost, if it did.
+    pub fn device_get_loss_diagnostics<B: GfxBackend>(
+        &self,
+        device_id: id::DeviceId,
+    ) -> Option<DeviceLossDiagnostics> {
+        span!(_guard, INFO, "Device::get_loss_diagnostics");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (device_guard, mut token) = hub.devices.read(&mut token);
+        device_guard[device_id].lock_life(&mut token).lost.clone()
+    }
+
     fn poll_devices<B: GfxBackend>(
         &self,
         force_wait: bool,
@@ -2899,6 +3199,9 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 resource::BufferMapState::Init { .. } | resource::BufferMapState::Active { .. } => {
                     panic!("Buffer already mapped")
                 }
+                resource::BufferMapState::Persistent { .. } => {
+                    panic!("Buffer is persistently mapped")
+                }
                 resource::BufferMapState::Waiting(_) => {
                     op.call_error();
                     return;
@@ -2944,7 +3247,8 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
 
         match buffer.map_state {
             resource::BufferMapState::Init { ptr, .. }
-            | resource::BufferMapState::Active { ptr, .. } => unsafe {
+            | resource::BufferMapState::Active { ptr, .. }
+            | resource::BufferMapState::Persistent { ptr, .. } => unsafe {
                 ptr.as_ptr().offset(offset as isize)
             },
             resource::BufferMapState::Idle | resource::BufferMapState::Waiting(_) => {
@@ -2954,6 +3258,78 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         }
     }
 
+    /// Make the host writes to the `range` of a persistently mapped buffer
+    /// visible to the following submissions.
+    pub fn buffer_flush_mapped_range<B: GfxBackend>(
+        &self,
+        buffer_id: id::BufferId,
+        range: Range<BufferAddress>,
+    ) {
+        span!(_guard, INFO, "Device::buffer_flush_mapped_range");
+
+        self.sync_persistent_buffer::<B>(buffer_id, range, HostMap::Write);
+    }
+
+    /// Make the writes of the finished submissions to the `range` of
+    /// a persistently mapped buffer visible to the host.
+    pub fn buffer_invalidate_mapped_range<B: GfxBackend>(
+        &self,
+        buffer_id: id::BufferId,
+        range: Range<BufferAddress>,
+    ) {
+        span!(_guard, INFO, "Device::buffer_invalidate_mapped_range");
+
+        self.sync_persistent_buffer::<B>(buffer_id, range, HostMap::Read);
+    }
+
+    fn sync_persistent_buffer<B: GfxBackend>(
+        &self,
+        buffer_id: id::BufferId,
+        range: Range<BufferAddress>,
+        kind: HostMap,
+    ) {
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (device_guard, mut token) = hub.devices.read(&mut token);
+        let (buffer_guard, _) = hub.buffers.read(&mut token);
+        let buffer = &buffer_guard[buffer_id];
+        let device = &device_guard[buffer.device_id.value];
+
+        assert!(
+            range.start <= range.end && range.end <= buffer.size,
+            "Range {:?} is out of bounds of the buffer of size {}",
+            range,
+            buffer.size
+        );
+        match buffer.map_state {
+            resource::BufferMapState::Persistent {
+                host,
+                sync_segment: None,
+                ..
+            } if host == kind => {}
+            resource::BufferMapState::Persistent {
+                host,
+                sync_segment: Some(ref sync_segment),
+                ..
+            } if host == kind => {
+                if let Err(e) = sync_persistent_range(
+                    &device.raw,
+                    buffer.memory.memory(),
+                    sync_segment,
+                    range,
+                    kind,
+                    device.hal_limits.non_coherent_atom_size as BufferAddress,
+                ) {
+                    log::error!("Failed to synchronize mapped range: {:?}", e);
+                }
+            }
+            resource::BufferMapState::Persistent { host, .. } => {
+                log::error!("Buffer is persistently mapped for {:?}", host);
+            }
+            _ => log::error!("Buffer is not persistently mapped"),
+        }
+    }
+
     pub fn buffer_unmap<B: GfxBackend>(&self, buffer_id: id::BufferId) {
         span!(_guard, INFO, "Device::buffer_unmap");
 
@@ -3026,6 +3402,10 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             resource::BufferMapState::Idle => {
                 log::error!("Buffer is not mapped");
             }
+            state @ resource::BufferMapState::Persistent { .. } => {
+                log::error!("Buffer is persistently mapped and can't be unmapped");
+                buffer.map_state = state;
+            }
             resource::BufferMapState::Waiting(_) => {}
             resource::BufferMapState::Active {
                 ptr,
@@ -3057,3 +3437,88 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         }
     }
 }
+
+/// A background thread that polls all the devices of a `Global` periodically,
+/// so that the map callbacks are fired and the finished submissions are cleaned up
+/// without the application polling the devices itself.
+///
+/// The thread is stopped and joined when this is dropped.
+#[derive(Debug)]
+pub struct MaintenanceThread {
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl MaintenanceThread {
+    /// Starts polling the devices of `global` every `interval`.
+    pub fn spawn<G>(global: Arc<Global<G>>, interval: Duration) -> Self
+    where
+        G: GlobalIdentityHandlerFactory,
+        Global<G>: Send + Sync + 'static,
+    {
+        let (stop, receiver) = mpsc::channel();
+        let handle = thread::Builder::new()
+            .name("wgpu-maintenance".to_string())
+            .spawn(move || {
+                // Any message, or the sender being dropped, stops the thread.
+                while let Err(mpsc::RecvTimeoutError::Timeout) = receiver.recv_timeout(interval) {
+                    global.poll_all_devices(false);
+                }
+            })
+            .expect("Failed to spawn the maintenance thread");
+        MaintenanceThread {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for MaintenanceThread {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            // Dropped from a map callback, which runs on the thread itself.
+            if handle.thread().id() == thread::current().id() {
+                return;
+            }
+            if handle.join().is_err() {
+                log::error!("Maintenance thread panicked");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_persistent_upload_buffer_memory_usage() {
+    let private_features = PrivateFeatures {
+        shader_validation: true,
+        portability_checks: true,
+        unified_memory: false,
+        anisotropic_filtering: false,
+        texture_d24_s8: false,
+        unaligned_buffer_copies: false,
+    };
+    let usage = buffer_memory_usage(
+        wgt::BufferUsage::PERSISTENT_MAP | wgt::BufferUsage::MAP_WRITE | wgt::BufferUsage::COPY_SRC,
+        wgt::Features::PERSISTENT_MAPPING,
+        private_features,
+    );
+    assert!(match usage {
+        gfx_memory::MemoryUsage::Staging { read_back: false } => true,
+        _ => false,
+    });
+}
+
+#[test]
+fn test_maintenance_thread_shutdown() {
+    let global = Arc::new(Global::new(
+        "test",
+        crate::hub::IdentityManagerFactory,
+        wgt::BackendBit::empty(),
+    ));
+    let maintenance = MaintenanceThread::spawn(global.clone(), Duration::from_millis(1));
+    thread::sleep(Duration::from_millis(10));
+    drop(maintenance);
+    // the thread has released its reference to the global
+    assert_eq!(Arc::strong_count(&global), 1);
+}
@@ -7,6 +7,7 @@ use crate::device::trace::Action;
 use crate::{
     command::{CommandAllocator, CommandBuffer, TextureCopyView, BITS_PER_BYTE},
     conv,
+    device::staging::{self, StagingBuffer},
     hub::{GfxBackend, Global, GlobalIdentityHandlerFactory, Token},
     id,
     resource::{BufferMapState, BufferUse, TextureUse},
@@ -16,11 +17,76 @@ use crate::{
 use gfx_memory::{Block, Heaps, MemoryBlock};
 use hal::{command::CommandBuffer as _, device::Device as _, queue::CommandQueue as _};
 use smallvec::SmallVec;
-use std::iter;
+use std::{iter, sync::atomic::Ordering};
+
+/// An image in host memory, to be copied into a texture.
+///
+/// The texels are tightly packed RGBA, 8 bits per channel, with non-premultiplied alpha.
+#[derive(Clone, Copy, Debug)]
+pub struct ExternalImage<'a> {
+    pub data: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Conversions to apply while copying an external image.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ExternalImageCopyOptions {
+    /// Flip the image vertically.
+    pub flip_y: bool,
+    /// Multiply the color channels by alpha.
+    pub premultiply_alpha: bool,
+}
+
+/// Convert the `image` into the layout of `format`, applying the `options`.
+fn convert_external_image(
+    image: &ExternalImage,
+    format: wgt::TextureFormat,
+    options: ExternalImageCopyOptions,
+) -> Vec<u8> {
+    use wgt::TextureFormat as Tf;
+
+    let swap_red_blue = match format {
+        Tf::Rgba8Unorm | Tf::Rgba8UnormSrgb => false,
+        Tf::Bgra8Unorm | Tf::Bgra8UnormSrgb => true,
+        _ => panic!(
+            "External images can't be copied into textures of format {:?}",
+            format
+        ),
+    };
+    let row_size = image.width as usize * 4;
+    assert_eq!(
+        image.data.len(),
+        row_size * image.height as usize,
+        "External image data size doesn't match its extent"
+    );
+
+    let mut result = Vec::with_capacity(image.data.len());
+    for y in 0..image.height as usize {
+        let src_y = if options.flip_y {
+            image.height as usize - 1 - y
+        } else {
+            y
+        };
+        let row = &image.data[src_y * row_size..(src_y + 1) * row_size];
+        for texel in row.chunks_exact(4) {
+            let mut rgba = [texel[0], texel[1], texel[2], texel[3]];
+            if options.premultiply_alpha {
+                for channel in rgba[..3].iter_mut() {
+                    *channel = ((*channel as u32 * rgba[3] as u32 + 127) / 255) as u8;
+                }
+            }
+            if swap_red_blue {
+                rgba.swap(0, 2);
+            }
+            result.extend_from_slice(&rgba);
+        }
+    }
+    result
+}
 
 struct StagingData<B: hal::Backend> {
-    buffer: B::Buffer,
-    memory: MemoryBlock<B>,
+    buffer: StagingBuffer<B>,
     comb: B::CommandBuffer,
 }
 
@@ -28,6 +94,7 @@ struct StagingData<B: hal::Backend> {
 pub(crate) struct PendingWrites<B: hal::Backend> {
     pub command_buffer: Option<B::CommandBuffer>,
     pub temp_buffers: Vec<(B::Buffer, MemoryBlock<B>)>,
+    pub staging_buffers: Vec<StagingBuffer<B>>,
 }
 
 impl<B: hal::Backend> PendingWrites<B> {
@@ -35,6 +102,7 @@ impl<B: hal::Backend> PendingWrites<B> {
         PendingWrites {
             command_buffer: None,
             temp_buffers: Vec::new(),
+            staging_buffers: Vec::new(),
         }
     }
 
@@ -53,6 +121,12 @@ impl<B: hal::Backend> PendingWrites<B> {
                 device.destroy_buffer(buffer);
             }
         }
+        for buffer in self.staging_buffers {
+            mem_allocator.free(device, buffer.memory);
+            unsafe {
+                device.destroy_buffer(buffer.raw);
+            }
+        }
     }
 
     pub fn consume_temp(&mut self, buffer: B::Buffer, memory: MemoryBlock<B>) {
@@ -60,7 +134,7 @@ impl<B: hal::Backend> PendingWrites<B> {
     }
 
     fn consume(&mut self, stage: StagingData<B>) {
-        self.temp_buffers.push((stage.buffer, stage.memory));
+        self.staging_buffers.push(stage.buffer);
         self.command_buffer = Some(stage.comb);
     }
 }
@@ -78,30 +152,39 @@ impl<B: hal::Backend> super::Device<B> {
     }
 
     fn prepare_stage(&mut self, size: wgt::BufferAddress) -> StagingData<B> {
-        let mut buffer = unsafe {
-            self.raw
-                .create_buffer(size, hal::buffer::Usage::TRANSFER_SRC)
-                .unwrap()
+        let size = staging::bucket_size(size);
+        let staging_pool = &mut self.life_tracker.get_mut().staging_pool;
+        let buffer = match staging_pool.acquire(size) {
+            Some(buffer) => buffer,
+            None => {
+                staging_pool.count_allocation();
+                let mut raw = unsafe {
+                    self.raw
+                        .create_buffer(size, hal::buffer::Usage::TRANSFER_SRC)
+                        .unwrap()
+                };
+                //TODO: do we need to transition into HOST_WRITE access first?
+                let requirements = unsafe { self.raw.get_buffer_requirements(&raw) };
+
+                let memory = self
+                    .mem_allocator
+                    .lock()
+                    .allocate(
+                        &self.raw,
+                        &requirements,
+                        gfx_memory::MemoryUsage::Staging { read_back: false },
+                        staging::memory_kind(size),
+                    )
+                    .unwrap();
+                unsafe {
+                    self.raw.set_buffer_name(&mut raw, "<write_buffer_temp>");
+                    self.raw
+                        .bind_buffer_memory(memory.memory(), memory.segment().offset, &mut raw)
+                        .unwrap();
+                }
+                StagingBuffer { raw, memory, size }
+            }
         };
-        //TODO: do we need to transition into HOST_WRITE access first?
-        let requirements = unsafe { self.raw.get_buffer_requirements(&buffer) };
-
-        let memory = self
-            .mem_allocator
-            .lock()
-            .allocate(
-                &self.raw,
-                &requirements,
-                gfx_memory::MemoryUsage::Staging { read_back: false },
-                gfx_memory::Kind::Linear,
-            )
-            .unwrap();
-        unsafe {
-            self.raw.set_buffer_name(&mut buffer, "<write_buffer_temp>");
-            self.raw
-                .bind_buffer_memory(memory.memory(), memory.segment().offset, &mut buffer)
-                .unwrap();
-        }
 
         let comb = match self.pending_writes.command_buffer.take() {
             Some(comb) => comb,
@@ -113,11 +196,7 @@ impl<B: hal::Backend> super::Device<B> {
                 comb
             }
         };
-        StagingData {
-            buffer,
-            memory,
-            comb,
-        }
+        StagingData { buffer, comb }
     }
 }
 
@@ -137,7 +216,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let mut token = Token::root();
         let (mut device_guard, mut token) = hub.devices.write(&mut token);
         let device = &mut device_guard[queue_id];
-        let (buffer_guard, _) = hub.buffers.read(&mut token);
+        let (mut buffer_guard, _) = hub.buffers.write(&mut token);
 
         #[cfg(feature = "trace")]
         match device.trace {
@@ -160,9 +239,83 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             return;
         }
 
+        let dst = &mut buffer_guard[buffer_id];
+        assert!(
+            dst.usage.contains(wgt::BufferUsage::COPY_DST),
+            "Write buffer usage {:?} must contain flag COPY_DST",
+            dst.usage
+        );
+        if !device.private_features.unaligned_buffer_copies {
+            assert_eq!(
+                data_size % wgt::COPY_BUFFER_ALIGNMENT,
+                0,
+                "Buffer write size {} must be a multiple of {}",
+                data_size,
+                wgt::COPY_BUFFER_ALIGNMENT,
+            );
+            assert_eq!(
+                buffer_offset % wgt::COPY_BUFFER_ALIGNMENT,
+                0,
+                "Buffer offset {} must be a multiple of {}",
+                buffer_offset,
+                wgt::COPY_BUFFER_ALIGNMENT,
+            );
+        } else if (data_size | buffer_offset) % wgt::COPY_BUFFER_ALIGNMENT != 0
+            && device.private_features.portability_checks
+        {
+            log::warn!(
+                "Buffer write of {} bytes at offset {} is not aligned to {}, which is not portable",
+                data_size,
+                buffer_offset,
+                wgt::COPY_BUFFER_ALIGNMENT
+            );
+        }
+        let destination_start_offset = buffer_offset;
+        let destination_end_offset = buffer_offset + data_size;
+        assert!(
+            destination_end_offset <= dst.size,
+            "Write buffer with indices {}..{} overruns destination buffer of size {}",
+            destination_start_offset,
+            destination_end_offset,
+            dst.size
+        );
+
+        // With unified memory, all the buffers that can be written to are host-visible.
+        // If the GPU is done with the buffer, there is nothing to order the write against.
+        let last_use = dst.life_guard.submission_index.load(Ordering::Acquire);
+        let is_mapped = match dst.map_state {
+            BufferMapState::Idle => false,
+            _ => true,
+        };
+        if device.private_features.unified_memory
+            && last_use <= device.last_completed_submission_index()
+            && !is_mapped
+        {
+            let segment = hal::memory::Segment {
+                offset: buffer_offset,
+                size: Some(data_size),
+            };
+            match dst.memory.map(&device.raw, segment) {
+                Ok(mut mapped) => {
+                    log::trace!("Writing directly into {:?}", buffer_id);
+                    unsafe { mapped.write(&device.raw, hal::memory::Segment::ALL) }
+                        .unwrap()
+                        .slice[..data.len()]
+                        .copy_from_slice(data);
+                    return;
+                }
+                Err(err) => log::warn!(
+                    "Unable to map {:?} for a direct write, falling back to staging: {:?}",
+                    buffer_id,
+                    err
+                ),
+            }
+        }
+
         let mut stage = device.prepare_stage(data_size);
         {
             let mut mapped = stage
+                .buffer
                 .memory
                 .map(&device.raw, hal::memory::Segment::ALL)
                 .unwrap();
@@ -177,37 +330,8 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             trackers
                 .buffers
                 .use_replace(&*buffer_guard, buffer_id, (), BufferUse::COPY_DST);
-        assert!(
-            dst.usage.contains(wgt::BufferUsage::COPY_DST),
-            "Write buffer usage {:?} must contain flag COPY_DST",
-            dst.usage
-        );
         dst.life_guard.use_at(device.active_submission_index + 1);
 
-        assert_eq!(
-            data_size % wgt::COPY_BUFFER_ALIGNMENT,
-            0,
-            "Buffer write size {} must be a multiple of {}",
-            data_size,
-            wgt::COPY_BUFFER_ALIGNMENT,
-        );
-        assert_eq!(
-            buffer_offset % wgt::COPY_BUFFER_ALIGNMENT,
-            0,
-            "Buffer offset {} must be a multiple of {}",
-            buffer_offset,
-            wgt::COPY_BUFFER_ALIGNMENT,
-        );
-        let destination_start_offset = buffer_offset;
-        let destination_end_offset = buffer_offset + data_size;
-        assert!(
-            destination_end_offset <= dst.size,
-            "Write buffer with indices {}..{} overruns destination buffer of size {}",
-            destination_start_offset,
-            destination_end_offset,
-            dst.size
-        );
-
         let region = hal::command::BufferCopy {
             src: 0,
             dst: buffer_offset,
@@ -219,7 +343,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 hal::memory::Dependencies::empty(),
                 iter::once(hal::memory::Barrier::Buffer {
                     states: hal::buffer::Access::HOST_WRITE..hal::buffer::Access::TRANSFER_READ,
-                    target: &stage.buffer,
+                    target: &stage.buffer.raw,
                     range: hal::buffer::SubRange::WHOLE,
                     families: None,
                 })
@@ -227,7 +351,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             );
             stage
                 .comb
-                .copy_buffer(&stage.buffer, &dst.raw, iter::once(region));
+                .copy_buffer(&stage.buffer.raw, &dst.raw, iter::once(region));
         }
 
         device.pending_writes.consume(stage);
@@ -266,7 +390,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             None => {}
         }
 
-        if size.width == 0 || size.height == 0 || size.width == 0 {
+        if size.width == 0 || size.height == 0 || size.depth == 0 {
             log::trace!("Ignoring write_texture of size 0");
             return;
         }
@@ -294,6 +418,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let mut stage = device.prepare_stage(stage_size);
         {
             let mut mapped = stage
+                .buffer
                 .memory
                 .map(&device.raw, hal::memory::Segment::ALL)
                 .unwrap();
@@ -350,14 +475,14 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 hal::memory::Dependencies::empty(),
                 iter::once(hal::memory::Barrier::Buffer {
                     states: hal::buffer::Access::HOST_WRITE..hal::buffer::Access::TRANSFER_READ,
-                    target: &stage.buffer,
+                    target: &stage.buffer.raw,
                     range: hal::buffer::SubRange::WHOLE,
                     families: None,
                 })
                 .chain(transition.map(|pending| pending.into_hal(dst))),
             );
             stage.comb.copy_buffer_to_image(
-                &stage.buffer,
+                &stage.buffer.raw,
                 &dst.raw,
                 hal::image::Layout::TransferDstOptimal,
                 iter::once(region),
@@ -392,6 +517,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             device.temp_suspected.clear();
             device.active_submission_index += 1;
             let submit_index = device.active_submission_index;
+            let mut debug_markers = Vec::new();
 
             let fence = {
                 let mut signal_swapchain_semaphores = SmallVec::<[_; 1]>::new();
@@ -399,6 +525,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 let (mut command_buffer_guard, mut token) = hub.command_buffers.write(&mut token);
 
                 {
+                    span!(_guard, DEBUG, "Queue::submit: stitch command buffers");
                     let (bind_group_guard, mut token) = hub.bind_groups.read(&mut token);
                     let (compute_pipe_guard, mut token) = hub.compute_pipelines.read(&mut token);
                     let (render_pipe_guard, mut token) = hub.render_pipelines.read(&mut token);
@@ -417,6 +544,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     // finish all the command buffers first
                     for &cmb_id in command_buffer_ids {
                         let comb = &mut command_buffer_guard[cmb_id];
+                        debug_markers.extend(comb.debug_markers.drain(..));
                         #[cfg(feature = "trace")]
                         match device.trace {
                             Some(ref trace) => trace
@@ -451,7 +579,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                                 device.temp_suspected.buffers.push(id);
                             } else {
                                 match buffer.map_state {
-                                    BufferMapState::Idle => (),
+                                    BufferMapState::Idle | BufferMapState::Persistent { .. } => (),
                                     _ => panic!("Buffer {:?} is still mapped", id),
                                 }
                             }
@@ -502,6 +630,7 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                             &comb.trackers,
                             &*buffer_guard,
                             &*texture_guard,
+                            &device.counters,
                         );
                         unsafe {
                             transit.finish();
@@ -513,7 +642,12 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 }
 
                 // now prepare the GPU submission
-                let fence = device.raw.create_fence(false).unwrap();
+                let mut fence = device.raw.create_fence(false).unwrap();
+                unsafe {
+                    device
+                        .raw
+                        .set_fence_name(&mut fence, &format!("Submission #{}", submit_index));
+                }
                 let submission = hal::queue::Submission {
                     command_buffers: pending_write_command_buffer.as_ref().into_iter().chain(
                         command_buffer_ids
@@ -527,11 +661,17 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 };
 
                 unsafe {
+                    span!(_guard, DEBUG, "Queue::submit: hal submission");
                     device.queue_group.queues[0].submit(submission, Some(&fence));
                 }
                 fence
             };
 
+            #[cfg(feature = "counters")]
+            {
+                device.last_counters = device.counters.take();
+            }
+
             if let Some(comb_raw) = pending_write_command_buffer {
                 device
                     .com_allocator
@@ -544,6 +684,8 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 fence,
                 &device.temp_suspected,
                 device.pending_writes.temp_buffers.drain(..),
+                device.pending_writes.staging_buffers.drain(..),
+                debug_markers,
             );
 
             // finally, return the command buffers to the allocator
@@ -559,6 +701,46 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
     }
 }
 
+impl<G: GlobalIdentityHandlerFactory> Global<G> {
+    /// Upload an image from host memory into a 2D region of the `destination`.
+    ///
+    /// The conversion is done on the CPU, and the result is written as if
+    /// by `queue_write_texture`.
+    //TODO: convert the color space for the sRGB destinations.
+    pub fn queue_copy_external_image_to_texture<B: GfxBackend>(
+        &self,
+        queue_id: id::QueueId,
+        source: &ExternalImage,
+        destination: &TextureCopyView,
+        options: ExternalImageCopyOptions,
+    ) {
+        span!(_guard, INFO, "Queue::copy_external_image_to_texture");
+
+        let format = {
+            let hub = B::hub(self);
+            let mut token = Token::root();
+            let (texture_guard, _) = hub.textures.read(&mut token);
+            texture_guard[destination.texture].format
+        };
+        let data = convert_external_image(source, format, options);
+        self.queue_write_texture::<B>(
+            queue_id,
+            destination,
+            &data,
+            &wgt::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: source.width * 4,
+                rows_per_image: source.height,
+            },
+            &wgt::Extent3d {
+                width: source.width,
+                height: source.height,
+                depth: 1,
+            },
+        );
+    }
+}
+
 fn get_lowest_common_denom(a: u32, b: u32) -> u32 {
     let gcd = if a >= b {
         get_greatest_common_divisor(a, b)
@@ -602,3 +784,32 @@ fn test_gcd() {
     assert_eq!(get_greatest_common_divisor(6, 4), 2);
     assert_eq!(get_greatest_common_divisor(7, 7), 7);
 }
+
+#[test]
+fn test_external_image_conversion() {
+    let data = [10, 20, 30, 255, 200, 100, 0, 0];
+    let image = ExternalImage {
+        data: &data,
+        width: 1,
+        height: 2,
+    };
+    assert_eq!(
+        convert_external_image(
+            &image,
+            wgt::TextureFormat::Rgba8Unorm,
+            ExternalImageCopyOptions::default()
+        ),
+        data.to_vec()
+    );
+    assert_eq!(
+        convert_external_image(
+            &image,
+            wgt::TextureFormat::Bgra8Unorm,
+            ExternalImageCopyOptions {
+                flip_y: true,
+                premultiply_alpha: true,
+            }
+        ),
+        vec![0, 0, 0, 0, 30, 20, 10, 255]
+    );
+}
@@ -0,0 +1,216 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::FastHashMap;
+
+use gfx_memory::{Heaps, MemoryBlock};
+use hal::device::Device as _;
+use parking_lot::Mutex;
+
+/// Smallest size of a pooled staging buffer.
+const MIN_BUCKET_SIZE: wgt::BufferAddress = 0x100;
+/// Staging buffers larger than this are not pooled.
+const MAX_BUCKET_SIZE: wgt::BufferAddress = 0x40_0000;
+/// Maximum total size of the idle buffers kept around.
+const MAX_IDLE_BYTES: wgt::BufferAddress = 0x100_0000;
+
+/// Statistics of the staging buffers used by `Queue::write_buffer` and `Queue::write_texture`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StagingPoolStats {
+    /// Number of staging buffers created.
+    pub allocations: usize,
+    /// Number of times a staging buffer was reused instead of creating a new one.
+    pub reuses: usize,
+    /// Number of idle buffers in the pool.
+    pub idle_buffers: usize,
+    /// Total size of the idle buffers in the pool, in bytes.
+    pub idle_bytes: wgt::BufferAddress,
+}
+
+#[derive(Debug)]
+pub(crate) struct StagingBuffer<B: hal::Backend> {
+    pub raw: B::Buffer,
+    pub memory: MemoryBlock<B>,
+    pub size: wgt::BufferAddress,
+}
+
+/// Anything that can be kept in a `StagingPool`.
+pub(crate) trait PooledBuffer {
+    fn size(&self) -> wgt::BufferAddress;
+}
+
+impl<B: hal::Backend> PooledBuffer for StagingBuffer<B> {
+    fn size(&self) -> wgt::BufferAddress {
+        self.size
+    }
+}
+
+/// Returns the size of the buffer to create for the `size` bytes of data.
+pub(crate) fn bucket_size(size: wgt::BufferAddress) -> wgt::BufferAddress {
+    if size > MAX_BUCKET_SIZE {
+        size
+    } else {
+        size.max(MIN_BUCKET_SIZE).next_power_of_two()
+    }
+}
+
+/// Returns the allocation kind for a staging buffer of the `bucket_size`.
+///
+/// Pooled buffers live for a long time, so they can't take a whole line
+/// of the linear allocator each.
+pub(crate) fn memory_kind(bucket_size: wgt::BufferAddress) -> gfx_memory::Kind {
+    if bucket_size > MAX_BUCKET_SIZE {
+        gfx_memory::Kind::Linear
+    } else {
+        gfx_memory::Kind::General
+    }
+}
+
+/// Staging buffers that are no longer used by the GPU, grouped by size.
+///
+/// The sizes are rounded up to the next power of two, so that a buffer
+/// can be reused for any write that fits into it.
+#[derive(Debug)]
+pub(crate) struct StagingPool<T> {
+    idle: FastHashMap<wgt::BufferAddress, Vec<T>>,
+    /// Buffers that are returned while the pool is full, waiting to be freed.
+    excess: Vec<T>,
+    stats: StagingPoolStats,
+}
+
+impl<T: PooledBuffer> StagingPool<T> {
+    pub fn new() -> Self {
+        StagingPool {
+            idle: FastHashMap::default(),
+            excess: Vec::new(),
+            stats: StagingPoolStats::default(),
+        }
+    }
+
+    /// Take an idle buffer of the `bucket_size` out of the pool.
+    pub fn acquire(&mut self, bucket_size: wgt::BufferAddress) -> Option<T> {
+        let buffer = self.idle.get_mut(&bucket_size)?.pop()?;
+        self.stats.reuses += 1;
+        self.stats.idle_buffers -= 1;
+        self.stats.idle_bytes -= buffer.size();
+        Some(buffer)
+    }
+
+    /// Register the creation of a new staging buffer.
+    pub fn count_allocation(&mut self) {
+        self.stats.allocations += 1;
+    }
+
+    /// Return a buffer, which is no longer used by the GPU, into the pool.
+    pub fn release(&mut self, buffer: T) {
+        let size = buffer.size();
+        if size > MAX_BUCKET_SIZE || self.stats.idle_bytes + size > MAX_IDLE_BYTES {
+            self.excess.push(buffer);
+        } else {
+            self.stats.idle_buffers += 1;
+            self.stats.idle_bytes += size;
+            self.idle.entry(size).or_default().push(buffer);
+        }
+    }
+
+    pub fn stats(&self) -> StagingPoolStats {
+        self.stats.clone()
+    }
+}
+
+impl<B: hal::Backend> StagingPool<StagingBuffer<B>> {
+    /// Free the buffers that didn't fit into the pool.
+    pub fn trim(&mut self, device: &B::Device, heaps_mutex: &Mutex<Heaps<B>>) {
+        if self.excess.is_empty() {
+            return;
+        }
+        let mut heaps = heaps_mutex.lock();
+        for buffer in self.excess.drain(..) {
+            unsafe {
+                device.destroy_buffer(buffer.raw);
+            }
+            heaps.free(device, buffer.memory);
+        }
+    }
+
+    /// Free all the buffers.
+    pub fn clear(&mut self, device: &B::Device, heaps_mutex: &Mutex<Heaps<B>>) {
+        for (_, buffers) in self.idle.drain() {
+            self.excess.extend(buffers);
+        }
+        self.stats.idle_buffers = 0;
+        self.stats.idle_bytes = 0;
+        self.trim(device, heaps_mutex);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct TestBuffer(wgt::BufferAddress);
+
+    impl PooledBuffer for TestBuffer {
+        fn size(&self) -> wgt::BufferAddress {
+            self.0
+        }
+    }
+
+    #[test]
+    fn bucket_sizes() {
+        assert_eq!(bucket_size(0), MIN_BUCKET_SIZE);
+        assert_eq!(bucket_size(1), MIN_BUCKET_SIZE);
+        assert_eq!(bucket_size(MIN_BUCKET_SIZE + 1), MIN_BUCKET_SIZE * 2);
+        assert_eq!(bucket_size(MAX_BUCKET_SIZE), MAX_BUCKET_SIZE);
+        assert_eq!(bucket_size(MAX_BUCKET_SIZE + 1), MAX_BUCKET_SIZE + 1);
+    }
+
+    #[test]
+    fn memory_kinds() {
+        let is_general = |size| match memory_kind(size) {
+            gfx_memory::Kind::General => true,
+            _ => false,
+        };
+        assert!(is_general(MIN_BUCKET_SIZE));
+        assert!(is_general(MAX_BUCKET_SIZE));
+        assert!(!is_general(MAX_BUCKET_SIZE + 1));
+    }
+
+    #[test]
+    fn acquire_released() {
+        let mut pool = StagingPool::new();
+        assert_eq!(pool.acquire(0x100), None);
+        pool.release(TestBuffer(0x100));
+        assert_eq!(pool.acquire(0x200), None);
+        assert_eq!(pool.acquire(0x100), Some(TestBuffer(0x100)));
+        assert_eq!(pool.acquire(0x100), None);
+        assert_eq!(
+            pool.stats(),
+            StagingPoolStats {
+                allocations: 0,
+                reuses: 1,
+                idle_buffers: 0,
+                idle_bytes: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn release_over_limits() {
+        let mut pool = StagingPool::new();
+        pool.release(TestBuffer(MAX_BUCKET_SIZE + 1));
+        assert_eq!(pool.stats().idle_buffers, 0);
+        assert_eq!(pool.excess, vec![TestBuffer(MAX_BUCKET_SIZE + 1)]);
+
+        let count = MAX_IDLE_BYTES / MAX_BUCKET_SIZE;
+        for _ in 0..count {
+            pool.release(TestBuffer(MAX_BUCKET_SIZE));
+        }
+        assert_eq!(pool.stats().idle_bytes, MAX_IDLE_BYTES);
+        pool.release(TestBuffer(MIN_BUCKET_SIZE));
+        assert_eq!(pool.stats().idle_buffers, count as usize);
+        assert_eq!(pool.excess.len(), 2);
+    }
+}
@@ -13,6 +13,39 @@ type FileName = String;
 
 pub const FILE_NAME: &str = "trace.ron";
 
+/// Version of the trace format, written in the header of the trace file.
+///
+/// Bump this whenever the actions or descriptors change.
+pub const VERSION: u32 = 4;
+/// Oldest version of the trace format that can still be replayed.
+///
+/// Raise this when a change breaks the compatibility with existing traces.
+/// Traces without a header are read as version 0.
+pub const MIN_COMPATIBLE_VERSION: u32 = 2;
+/// Start of the first line of the trace file, which is followed by the format version.
+pub const HEADER_PREFIX: &str = "// wgpu trace version ";
+
+/// Returns true if a trace of the given format version can be replayed.
+pub fn is_compatible(version: u32) -> bool {
+    MIN_COMPATIBLE_VERSION <= version && version <= VERSION
+}
+
+/// Split the contents of a trace file into the format version and the list of actions.
+///
+/// This allows checking the version before parsing the actions.
+pub fn split_header(text: &str) -> (u32, &str) {
+    if text.starts_with(HEADER_PREFIX) {
+        let (line, rest) = match text.find('\n') {
+            Some(pos) => (&text[..pos], &text[pos + 1..]),
+            None => (text, ""),
+        };
+        if let Ok(version) = line[HEADER_PREFIX.len()..].trim().parse() {
+            return (version, rest);
+        }
+    }
+    (0, text)
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "trace", derive(serde::Serialize))]
 #[cfg_attr(feature = "replay", derive(serde::Deserialize))]
@@ -78,6 +111,8 @@ pub struct VertexBufferDescriptor {
 pub struct VertexStateDescriptor {
     pub index_format: wgt::IndexFormat,
     pub vertex_buffers: Vec<VertexBufferDescriptor>,
+    #[cfg_attr(feature = "replay", serde(default))]
+    pub primitive_restart: bool,
 }
 
 #[derive(Debug)]
@@ -238,6 +273,11 @@ pub enum Command {
         dst: crate::command::TextureCopyView,
         size: wgt::Extent3d,
     },
+    ResolveTexture {
+        src: crate::command::TextureCopyView,
+        dst: crate::command::TextureCopyView,
+        size: wgt::Extent3d,
+    },
     RunComputePass {
         base: crate::command::BasePass<crate::command::ComputeCommand>,
     },
@@ -262,6 +302,7 @@ impl Trace {
     pub fn new(path: &std::path::Path) -> Result<Self, std::io::Error> {
         log::info!("Tracing into '{:?}'", path);
         let mut file = std::fs::File::create(path.join(FILE_NAME))?;
+        writeln!(file, "{}{}", HEADER_PREFIX, VERSION)?;
         file.write_all(b"[\n")?;
         Ok(Trace {
             path: path.to_path_buf(),
@@ -693,6 +693,7 @@ impl<G: GlobalIdentityHandlerFactory> Drop for Global<G> {
 pub trait GfxBackend: hal::Backend {
     const VARIANT: Backend;
     fn hub<G: GlobalIdentityHandlerFactory>(global: &Global<G>) -> &Hub<Self, G>;
+    fn get_surface(surface: &Surface) -> &Self::Surface;
     fn get_surface_mut(surface: &mut Surface) -> &mut Self::Surface;
 }
 
@@ -705,6 +706,9 @@ impl GfxBackend for backend::Vulkan {
     fn hub<G: GlobalIdentityHandlerFactory>(global: &Global<G>) -> &Hub<Self, G> {
         &global.hubs.vulkan
     }
+    fn get_surface(surface: &Surface) -> &Self::Surface {
+        surface.vulkan.as_ref().unwrap()
+    }
     fn get_surface_mut(surface: &mut Surface) -> &mut Self::Surface {
         surface.vulkan.as_mut().unwrap()
     }
@@ -716,6 +720,9 @@ impl GfxBackend for backend::Metal {
     fn hub<G: GlobalIdentityHandlerFactory>(global: &Global<G>) -> &Hub<Self, G> {
         &global.hubs.metal
     }
+    fn get_surface(surface: &Surface) -> &Self::Surface {
+        surface.metal.as_ref().unwrap()
+    }
     fn get_surface_mut(surface: &mut Surface) -> &mut Self::Surface {
         surface.metal.as_mut().unwrap()
     }
@@ -727,6 +734,9 @@ impl GfxBackend for backend::Dx12 {
     fn hub<G: GlobalIdentityHandlerFactory>(global: &Global<G>) -> &Hub<Self, G> {
         &global.hubs.dx12
     }
+    fn get_surface(surface: &Surface) -> &Self::Surface {
+        surface.dx12.as_ref().unwrap()
+    }
     fn get_surface_mut(surface: &mut Surface) -> &mut Self::Surface {
         surface.dx12.as_mut().unwrap()
     }
@@ -738,6 +748,9 @@ impl GfxBackend for backend::Dx11 {
     fn hub<G: GlobalIdentityHandlerFactory>(global: &Global<G>) -> &Hub<Self, G> {
         &global.hubs.dx11
     }
+    fn get_surface(surface: &Surface) -> &Self::Surface {
+        surface.dx11.as_ref().unwrap()
+    }
     fn get_surface_mut(surface: &mut Surface) -> &mut Self::Surface {
         surface.dx11.as_mut().unwrap()
     }
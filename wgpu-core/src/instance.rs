@@ -71,24 +71,24 @@ impl Instance {
         }
     }
 
-    pub(crate) fn destroy_surface(&mut self, surface: Surface) {
+    pub(crate) fn destroy_surface(&self, surface: Surface) {
         backends_map! {
             let map = |(surface_backend, self_backend)| {
                 unsafe {
                     if let Some(suf) = surface_backend {
-                        self_backend.as_mut().unwrap().destroy_surface(suf);
+                        self_backend.as_ref().unwrap().destroy_surface(suf);
                     }
                 }
             };
 
             #[vulkan]
-            map((surface.vulkan, &mut self.vulkan)),
+            map((surface.vulkan, &self.vulkan)),
             #[metal]
-            map((surface.metal, &mut self.metal)),
+            map((surface.metal, &self.metal)),
             #[dx12]
-            map((surface.dx12, &mut self.dx12)),
+            map((surface.dx12, &self.dx12)),
             #[dx11]
-            map((surface.dx11, &mut self.dx11)),
+            map((surface.dx11, &self.dx11)),
         }
     }
 }
@@ -126,7 +126,8 @@ impl<B: hal::Backend> Adapter<B> {
 
         let mut features = wgt::Features::default()
             | wgt::Features::MAPPABLE_PRIMARY_BUFFERS
-            | wgt::Features::PUSH_CONSTANTS;
+            | wgt::Features::PUSH_CONSTANTS
+            | wgt::Features::PERSISTENT_MAPPING;
         features.set(
             wgt::Features::SAMPLED_TEXTURE_BINDING_ARRAY,
             adapter_features.contains(hal::Features::TEXTURE_DESCRIPTOR_ARRAY),
@@ -151,6 +152,22 @@ impl<B: hal::Backend> Adapter<B> {
             wgt::Features::MULTI_DRAW_INDIRECT_COUNT,
             adapter_features.contains(hal::Features::DRAW_INDIRECT_COUNT),
         );
+        features.set(
+            wgt::Features::SHADER_INT16,
+            adapter_features.contains(hal::Features::SHADER_INT16),
+        );
+        features.set(
+            wgt::Features::MULTI_VIEWPORT,
+            adapter_features.contains(hal::Features::MULTI_VIEWPORTS),
+        );
+        features.set(
+            wgt::Features::INDIRECT_FIRST_INSTANCE,
+            adapter_features.contains(hal::Features::DRAW_INDIRECT_FIRST_INSTANCE),
+        );
+        features.set(
+            wgt::Features::DEPTH_BOUNDS_TEST,
+            adapter_features.contains(hal::Features::DEPTH_BOUNDS),
+        );
 
         let adapter_limits = raw.physical_device.limits();
 
@@ -220,10 +237,22 @@ pub struct AdapterInfo {
     pub device_type: DeviceType,
     /// Backend used for device
     pub backend: Backend,
+    /// Total size of the memory heaps that are local to the device, in bytes
+    pub device_local_memory: u64,
+    /// Total size of the memory heaps that are visible to the host, in bytes
+    pub host_visible_memory: u64,
+    /// All the device-local memory is also visible to the host
+    pub unified_memory: bool,
 }
 
 impl AdapterInfo {
-    fn from_gfx(adapter_info: HalAdapterInfo, backend: Backend) -> Self {
+    fn from_gfx(
+        adapter_info: HalAdapterInfo,
+        memory_properties: &hal::adapter::MemoryProperties,
+        backend: Backend,
+    ) -> Self {
+        use hal::memory::Properties as P;
+
         let HalAdapterInfo {
             name,
             vendor,
@@ -231,23 +260,126 @@ impl AdapterInfo {
             device_type,
         } = adapter_info;
 
+        // Sum up the heaps that have at least one memory type with the properties.
+        let heap_size = |properties: P| -> u64 {
+            memory_properties
+                .memory_heaps
+                .iter()
+                .enumerate()
+                .filter(|&(index, _)| {
+                    memory_properties
+                        .memory_types
+                        .iter()
+                        .any(|ty| ty.heap_index == index && ty.properties.contains(properties))
+                })
+                .map(|(_, &size)| size)
+                .sum()
+        };
+
         AdapterInfo {
             name,
             vendor,
             device,
             device_type: device_type.into(),
             backend,
+            device_local_memory: heap_size(P::DEVICE_LOCAL),
+            host_visible_memory: heap_size(P::CPU_VISIBLE),
+            unified_memory: is_unified_memory(memory_properties),
         }
     }
 }
 
+/// Everything known about an adapter, in a form suitable for system reports.
+///
+/// With the `trace` feature enabled, this can be serialized into any
+/// serde format, such as JSON or RON.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "trace", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct AdapterReport {
+    pub info: AdapterInfo,
+    pub features: wgt::Features,
+    pub limits: wgt::Limits,
+}
+
+/// Returns the rank of a device type for the power preference, lower being better.
+fn device_type_ranking(power_preference: PowerPreference) -> impl Fn(&HalDeviceType) -> u8 {
+    // Positions of the integrated, discrete, other, and virtual GPUs.
+    let order = match power_preference {
+        PowerPreference::Default => match power::is_battery_discharging() {
+            Ok(false) => [1, 0, 2, 3],
+            Ok(true) => [0, 1, 2, 3],
+            Err(err) => {
+                log::debug!(
+                    "Power info unavailable, preferring integrated gpu ({})",
+                    err
+                );
+                [0, 1, 2, 3]
+            }
+        },
+        PowerPreference::LowPower => [0, 2, 1, 3],
+        PowerPreference::HighPerformance => [2, 0, 1, 3],
+    };
+    move |device_type| match *device_type {
+        HalDeviceType::IntegratedGpu => order[0],
+        HalDeviceType::DiscreteGpu => order[1],
+        HalDeviceType::VirtualGpu => order[3],
+        _ => order[2],
+    }
+}
+
+/// Returns true if the adapter is suitable for `force_fallback_adapter`.
+fn is_fallback_adapter(info: &HalAdapterInfo) -> bool {
+    info.device_type == HalDeviceType::Cpu
+}
+
+/// Returns true if there is device-local memory, and all of it is also host-visible.
+fn is_unified_memory(memory_properties: &hal::adapter::MemoryProperties) -> bool {
+    let mut device_local = memory_properties
+        .memory_types
+        .iter()
+        .filter(|ty| {
+            ty.properties
+                .contains(hal::memory::Properties::DEVICE_LOCAL)
+        })
+        .peekable();
+    device_local.peek().is_some()
+        && device_local.all(|ty| ty.properties.contains(hal::memory::Properties::CPU_VISIBLE))
+}
+
+/// Returns the features to enable on the device: all the required ones,
+/// and the optional ones that the adapter supports.
+fn resolve_device_features(
+    adapter_features: wgt::Features,
+    desc: &DeviceDescriptor,
+) -> Result<wgt::Features, RequestDeviceError> {
+    // Verify all features were exposed by the adapter
+    if !adapter_features.contains(desc.features) {
+        return Err(RequestDeviceError::UnsupportedFeature(
+            desc.features - adapter_features,
+        ));
+    }
+    let optional_features = desc.optional_features & adapter_features;
+    if optional_features != desc.optional_features {
+        log::info!(
+            "Optional features are not supported: {:?}",
+            desc.optional_features - adapter_features
+        );
+    }
+    Ok(desc.features | optional_features)
+}
+
 #[derive(Clone, Debug, PartialEq)]
 /// Error when requesting a device from the adaptor
 pub enum RequestDeviceError {
     /// Unsupported feature extension was requested
     UnsupportedFeature(wgt::Features),
-    /// Requested device limits were exceeded
-    LimitsExceeded,
+    /// Requested device limit is above the one supported by the adapter
+    LimitsExceeded {
+        name: &'static str,
+        requested: u32,
+        allowed: u32,
+    },
 }
 
 impl Display for RequestDeviceError {
@@ -258,9 +390,15 @@ impl Display for RequestDeviceError {
                 "Cannot enable features that adapter doesn't support. Unsupported extensions: {:?}",
                 features
             ),
-            RequestDeviceError::LimitsExceeded => {
-                write!(f, "Some of the requested limits are not supported",)
-            }
+            RequestDeviceError::LimitsExceeded {
+                name,
+                requested,
+                allowed,
+            } => write!(
+                f,
+                "Requested limit {} = {} is above the adapter maximum of {}",
+                name, requested, allowed
+            ),
         }
     }
 }
@@ -349,6 +487,61 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         self.surfaces.register_identity(id_in, surface, &mut token)
     }
 
+    /// Destroy the surface, together with the swap chains created for it.
+    ///
+    /// On Android, the native window goes away when the activity is paused,
+    /// so the surface needs to be dropped at this point and re-created
+    /// from the new window when the activity is resumed.
+    pub fn surface_drop(&self, surface_id: SurfaceId) {
+        span!(_guard, INFO, "Surface::drop");
+
+        let mut token = Token::root();
+        let (mut surface, mut token) = self.surfaces.unregister(surface_id, &mut token);
+        backends! {
+            #[vulkan] {
+                self.surface_drop_swap_chain::<backend::Vulkan>(surface_id, &mut surface, &mut token);
+            }
+            #[metal] {
+                self.surface_drop_swap_chain::<backend::Metal>(surface_id, &mut surface, &mut token);
+            }
+            #[dx12] {
+                self.surface_drop_swap_chain::<backend::Dx12>(surface_id, &mut surface, &mut token);
+            }
+            #[dx11] {
+                self.surface_drop_swap_chain::<backend::Dx11>(surface_id, &mut surface, &mut token);
+            }
+        }
+        self.instance.destroy_surface(surface);
+    }
+
+    fn surface_drop_swap_chain<B: GfxBackend>(
+        &self,
+        surface_id: SurfaceId,
+        surface: &mut Surface,
+        token: &mut Token<Surface>,
+    ) {
+        use hal::{device::Device as _, window::PresentationSurface as _};
+
+        let hub = B::hub(self);
+        let (device_guard, mut token) = hub.devices.read(token);
+        let (mut swap_chain_guard, _) = hub.swap_chains.write(&mut token);
+        if let Some(sc) = swap_chain_guard.remove(surface_id.to_swap_chain_id(B::VARIANT)) {
+            assert!(
+                sc.acquired_view_id.is_none(),
+                "SwapChainOutput must be dropped before the surface"
+            );
+            let device = &device_guard[sc.device_id.value];
+            // The presented frames may still be in flight.
+            if let Err(e) = device.raw.wait_idle() {
+                log::error!("Unable to wait for the device to be idle: {:?}", e);
+            }
+            unsafe {
+                device.raw.destroy_semaphore(sc.semaphore);
+                B::get_surface_mut(surface).unconfigure_swapchain(&device.raw);
+            }
+        }
+    }
+
     pub fn enumerate_adapters(&self, inputs: AdapterInputs<Input<G, AdapterId>>) -> Vec<AdapterId> {
         span!(_guard, INFO, "Instance::enumerate_adapters");
 
@@ -386,6 +579,91 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         adapters
     }
 
+    /// Enumerate the adapters that can present to the `compatible_surface`, if one is given,
+    /// ordered from the most suitable one for the `power_preference`.
+    pub fn enumerate_adapters_for_surface(
+        &self,
+        desc: &RequestAdapterOptions,
+        inputs: AdapterInputs<Input<G, AdapterId>>,
+    ) -> Vec<AdapterId> {
+        span!(_guard, INFO, "Instance::enumerate_adapters_for_surface");
+
+        let instance = &self.instance;
+        let mut token = Token::root();
+        let (surface_guard, mut token) = self.surfaces.read(&mut token);
+        let compatible_surface = desc.compatible_surface.map(|id| &surface_guard[id]);
+        let rank = device_type_ranking(desc.power_preference);
+        let mut adapters = Vec::new();
+
+        backends_map! {
+            let map = |(instance_field, backend, backend_info, backend_hub, surface_backend)| {
+                if let Some(inst) = instance_field {
+                    if let Some(id_backend) = inputs.find(backend) {
+                        let surface = compatible_surface.map(surface_backend);
+                        for raw in inst.enumerate_adapters() {
+                            let is_compatible = match surface {
+                                None => true,
+                                Some(None) => false,
+                                Some(Some(suf)) => raw
+                                    .queue_families
+                                    .iter()
+                                    .find(|qf| qf.queue_type().supports_graphics())
+                                    .map_or(false, |qf| suf.supports_queue_family(qf)),
+                            };
+                            if !is_compatible
+                                || (desc.force_fallback_adapter && !is_fallback_adapter(&raw.info))
+                            {
+                                continue;
+                            }
+                            let adapter_rank = rank(&raw.info.device_type);
+                            let adapter = Adapter::new(raw);
+                            log::info!("Adapter {} {:?}", backend_info, adapter.raw.info);
+                            let id = backend_hub(self).adapters.register_identity(
+                                id_backend.clone(),
+                                adapter,
+                                &mut token,
+                            );
+                            adapters.push((adapter_rank, id));
+                        }
+                    }
+                }
+            };
+
+            #[vulkan]
+            map((&instance.vulkan, Backend::Vulkan, "Vulkan", backend::Vulkan::hub, {
+                fn surface_vulkan(surf: &Surface) -> Option<&GfxSurface<backend::Vulkan>> {
+                    surf.vulkan.as_ref()
+                }
+                surface_vulkan
+            })),
+            #[metal]
+            map((&instance.metal, Backend::Metal, "Metal", backend::Metal::hub, {
+                fn surface_metal(surf: &Surface) -> Option<&GfxSurface<backend::Metal>> {
+                    surf.metal.as_ref()
+                }
+                surface_metal
+            })),
+            #[dx12]
+            map((&instance.dx12, Backend::Dx12, "Dx12", backend::Dx12::hub, {
+                fn surface_dx12(surf: &Surface) -> Option<&GfxSurface<backend::Dx12>> {
+                    surf.dx12.as_ref()
+                }
+                surface_dx12
+            })),
+            #[dx11]
+            map((&instance.dx11, Backend::Dx11, "Dx11", backend::Dx11::hub, {
+                fn surface_dx11(surf: &Surface) -> Option<&GfxSurface<backend::Dx11>> {
+                    surf.dx11.as_ref()
+                }
+                surface_dx11
+            })),
+        }
+
+        // The sort is stable, so the backend order is kept for the same device types.
+        adapters.sort_by_key(|&(adapter_rank, _)| adapter_rank);
+        adapters.into_iter().map(|(_, id)| id).collect()
+    }
+
     pub fn pick_adapter(
         &self,
         desc: &RequestAdapterOptions,
@@ -409,6 +687,9 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 match instance_backend {
                     Some(ref inst) if id_backend.is_some() => {
                         let mut adapters = inst.enumerate_adapters();
+                        if desc.force_fallback_adapter {
+                            adapters.retain(|a| is_fallback_adapter(&a.info));
+                        }
                         if let Some(surface_backend) = compatible_surface.and_then(surface_backend) {
                             adapters.retain(|a| {
                                 a.queue_families
@@ -461,42 +742,12 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             return None;
         }
 
-        let (mut integrated, mut discrete, mut virt, mut other) = (None, None, None, None);
-
-        for (i, ty) in device_types.into_iter().enumerate() {
-            match ty {
-                hal::adapter::DeviceType::IntegratedGpu => {
-                    integrated = integrated.or(Some(i));
-                }
-                hal::adapter::DeviceType::DiscreteGpu => {
-                    discrete = discrete.or(Some(i));
-                }
-                hal::adapter::DeviceType::VirtualGpu => {
-                    virt = virt.or(Some(i));
-                }
-                _ => {
-                    other = other.or(Some(i));
-                }
-            }
-        }
-
-        let preferred_gpu = match desc.power_preference {
-            PowerPreference::Default => match power::is_battery_discharging() {
-                Ok(false) => discrete.or(integrated).or(other).or(virt),
-                Ok(true) => integrated.or(discrete).or(other).or(virt),
-                Err(err) => {
-                    log::debug!(
-                        "Power info unavailable, preferring integrated gpu ({})",
-                        err
-                    );
-                    integrated.or(discrete).or(other).or(virt)
-                }
-            },
-            PowerPreference::LowPower => integrated.or(other).or(discrete).or(virt),
-            PowerPreference::HighPerformance => discrete.or(other).or(integrated).or(virt),
-        };
-
-        let mut selected = preferred_gpu.unwrap_or(0);
+        let rank = device_type_ranking(desc.power_preference);
+        let mut selected = device_types
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, ty)| rank(ty))
+            .map_or(0, |(i, _)| i);
 
         backends_map! {
             let map = |(info_adapter, id_backend, mut adapters_backend, backend_hub)| {
@@ -541,7 +792,11 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         let mut token = Token::root();
         let (adapter_guard, _) = hub.adapters.read(&mut token);
         let adapter = &adapter_guard[adapter_id];
-        AdapterInfo::from_gfx(adapter.raw.info.clone(), adapter_id.backend())
+        AdapterInfo::from_gfx(
+            adapter.raw.info.clone(),
+            &adapter.raw.physical_device.memory_properties(),
+            adapter_id.backend(),
+        )
     }
 
     pub fn adapter_features<B: GfxBackend>(&self, adapter_id: AdapterId) -> wgt::Features {
@@ -566,6 +821,59 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         adapter.limits.clone()
     }
 
+    /// Returns the present modes that a swap chain for the surface can use on this adapter.
+    ///
+    /// Modes missing from the list fall back to `Fifo` when the swap chain is created.
+    pub fn adapter_get_present_modes<B: GfxBackend>(
+        &self,
+        adapter_id: AdapterId,
+        surface_id: SurfaceId,
+    ) -> Vec<wgt::PresentMode> {
+        span!(_guard, INFO, "Adapter::get_present_modes");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (surface_guard, mut token) = self.surfaces.read(&mut token);
+        let (adapter_guard, _) = hub.adapters.read(&mut token);
+        let adapter = &adapter_guard[adapter_id];
+        let surface = B::get_surface(&surface_guard[surface_id]);
+        let caps = surface.capabilities(&adapter.raw.physical_device);
+
+        //TODO: report `Immediate` on DX12 when DXGI supports tearing
+        // (`DXGI_FEATURE_PRESENT_ALLOW_TEARING`) but the backend doesn't expose it.
+        [
+            (wgt::PresentMode::Fifo, hal::window::PresentMode::FIFO),
+            (wgt::PresentMode::Mailbox, hal::window::PresentMode::MAILBOX),
+            (
+                wgt::PresentMode::Immediate,
+                hal::window::PresentMode::IMMEDIATE,
+            ),
+        ]
+        .iter()
+        .filter(|&&(_, hal_mode)| caps.present_modes.contains(hal_mode))
+        .map(|&(mode, _)| mode)
+        .collect()
+    }
+
+    pub fn adapter_get_report<B: GfxBackend>(&self, adapter_id: AdapterId) -> AdapterReport {
+        span!(_guard, INFO, "Adapter::get_report");
+
+        let hub = B::hub(self);
+        let mut token = Token::root();
+        let (adapter_guard, _) = hub.adapters.read(&mut token);
+        let adapter = &adapter_guard[adapter_id];
+
+        AdapterReport {
+            info: AdapterInfo::from_gfx(
+                adapter.raw.info.clone(),
+                &adapter.raw.physical_device.memory_properties(),
+                adapter_id.backend(),
+            ),
+            features: adapter.features,
+            limits: adapter.limits.clone(),
+        }
+    }
+
     pub fn adapter_destroy<B: GfxBackend>(&self, adapter_id: AdapterId) {
         span!(_guard, INFO, "Adapter::drop");
 
@@ -604,12 +912,13 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             let adapter = &adapter_guard[adapter_id];
             let phd = &adapter.raw.physical_device;
 
-            // Verify all features were exposed by the adapter
-            if !adapter.features.contains(desc.features) {
-                return Err(RequestDeviceError::UnsupportedFeature(
-                    desc.features - adapter.features,
-                ));
-            }
+            // From here on, the supported optional features are the same as the required ones.
+            // They are reported back by `device_features`.
+            let desc = &DeviceDescriptor {
+                features: resolve_device_features(adapter.features, desc)?,
+                optional_features: wgt::Features::empty(),
+                ..desc.clone()
+            };
 
             // Verify feature preconditions
             if desc
@@ -679,6 +988,24 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                     .features
                     .contains(wgt::Features::MULTI_DRAW_INDIRECT_COUNT),
             );
+            enabled_features.set(
+                hal::Features::SHADER_INT16,
+                adapter.features.contains(wgt::Features::SHADER_INT16),
+            );
+            enabled_features.set(
+                hal::Features::MULTI_VIEWPORTS,
+                adapter.features.contains(wgt::Features::MULTI_VIEWPORT),
+            );
+            enabled_features.set(
+                hal::Features::DRAW_INDIRECT_FIRST_INSTANCE,
+                adapter
+                    .features
+                    .contains(wgt::Features::INDIRECT_FIRST_INSTANCE),
+            );
+            enabled_features.set(
+                hal::Features::DEPTH_BOUNDS,
+                adapter.features.contains(wgt::Features::DEPTH_BOUNDS_TEST),
+            );
 
             let family = adapter
                 .raw
@@ -699,8 +1026,12 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
                 BIND_BUFFER_ALIGNMENT % limits.min_uniform_buffer_offset_alignment,
                 "Adapter uniform buffer offset alignment not compatible with WGPU"
             );
-            if adapter.limits < desc.limits {
-                return Err(RequestDeviceError::LimitsExceeded);
+            if let Some((name, requested, allowed)) = desc.limits.first_exceeding(&adapter.limits) {
+                return Err(RequestDeviceError::LimitsExceeded {
+                    name,
+                    requested,
+                    allowed,
+                });
             }
 
             let mem_props = phd.memory_properties();
@@ -709,11 +1040,18 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
             }
             let private_features = PrivateFeatures {
                 shader_validation: desc.shader_validation,
+                portability_checks: desc.portability_checks,
+                //TODO: also write directly into the host-visible device-local
+                // heaps of discrete GPUs, when they are large enough.
+                unified_memory: is_unified_memory(&mem_props),
                 anisotropic_filtering: enabled_features.contains(hal::Features::SAMPLER_ANISOTROPY),
                 texture_d24_s8: phd
                     .format_properties(Some(hal::format::Format::D24UnormS8Uint))
                     .optimal_tiling
                     .contains(hal::format::ImageFeature::DEPTH_STENCIL_ATTACHMENT),
+                //TODO: enable once unaligned copies are split into an aligned body
+                // and a staged head and tail, so that they work on every backend.
+                unaligned_buffer_copies: false,
             };
 
             Device::new(
@@ -734,3 +1072,62 @@ impl<G: GlobalIdentityHandlerFactory> Global<G> {
         Ok(hub.devices.register_identity(id_in, device, &mut token))
     }
 }
+
+#[cfg(test)]
+fn test_memory_properties(types: &[hal::memory::Properties]) -> hal::adapter::MemoryProperties {
+    hal::adapter::MemoryProperties {
+        memory_types: types
+            .iter()
+            .map(|&properties| hal::adapter::MemoryType {
+                properties,
+                heap_index: 0,
+            })
+            .collect(),
+        memory_heaps: vec![1 << 30],
+    }
+}
+
+#[test]
+fn test_unified_memory_requires_device_local() {
+    assert!(!is_unified_memory(&test_memory_properties(&[])));
+    assert!(!is_unified_memory(&test_memory_properties(&[
+        hal::memory::Properties::CPU_VISIBLE | hal::memory::Properties::COHERENT
+    ])));
+}
+
+#[test]
+fn test_unified_memory_mixed() {
+    use hal::memory::Properties as P;
+    assert!(is_unified_memory(&test_memory_properties(&[
+        P::DEVICE_LOCAL | P::CPU_VISIBLE,
+        P::CPU_VISIBLE | P::COHERENT,
+    ])));
+    assert!(!is_unified_memory(&test_memory_properties(&[
+        P::DEVICE_LOCAL,
+        P::DEVICE_LOCAL | P::CPU_VISIBLE,
+    ])));
+}
+
+#[test]
+fn test_optional_features() {
+    use wgt::Features as F;
+    let adapter_features = F::SHADER_INT16 | F::DEPTH_BOUNDS_TEST;
+    let desc = DeviceDescriptor {
+        features: F::SHADER_INT16,
+        optional_features: F::DEPTH_BOUNDS_TEST | F::MULTI_VIEWPORT,
+        ..DeviceDescriptor::default()
+    };
+    assert_eq!(
+        resolve_device_features(adapter_features, &desc),
+        Ok(F::SHADER_INT16 | F::DEPTH_BOUNDS_TEST)
+    );
+
+    let desc = DeviceDescriptor {
+        features: F::MULTI_VIEWPORT,
+        ..DeviceDescriptor::default()
+    };
+    assert_eq!(
+        resolve_device_features(adapter_features, &desc),
+        Err(RequestDeviceError::UnsupportedFeature(F::MULTI_VIEWPORT))
+    );
+}
@@ -29,8 +29,11 @@ pub mod backend {
 }
 
 pub mod binding_model;
+#[cfg(feature = "capture")]
+pub mod capture;
 pub mod command;
 mod conv;
+pub mod counters;
 pub mod device;
 pub mod hub;
 pub mod id;
@@ -211,8 +214,14 @@ struct Stored<T> {
 #[derive(Clone, Copy, Debug)]
 struct PrivateFeatures {
     shader_validation: bool,
+    portability_checks: bool,
+    /// All the device-local memory is also host-visible.
+    unified_memory: bool,
     anisotropic_filtering: bool,
     texture_d24_s8: bool,
+    /// Buffer copies can have sizes and offsets that are not
+    /// multiples of `COPY_BUFFER_ALIGNMENT`.
+    unaligned_buffer_copies: bool,
 }
 
 #[macro_export]
@@ -1,4 +1,6 @@
 #[cfg(feature = "subscriber")]
+pub mod profiler;
+#[cfg(feature = "subscriber")]
 pub mod subscriber;
 
 #[macro_export]
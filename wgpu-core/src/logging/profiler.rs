@@ -0,0 +1,105 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
+};
+use tracing::{span, Subscriber};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+/// A single timed scope, along with all the scopes that were entered while it was active.
+#[derive(Clone, Debug)]
+pub struct ProfileScope {
+    /// Name of the span, e.g. `CommandEncoder::run_render_pass`.
+    pub name: &'static str,
+    /// CPU time spent between entering and exiting the span.
+    pub cpu_time: Duration,
+    /// Scopes nested inside this one, in the order they were entered.
+    pub children: Vec<ProfileScope>,
+}
+
+#[derive(Debug)]
+struct OpenScope {
+    name: &'static str,
+    start: Instant,
+    children: Vec<ProfileScope>,
+}
+
+#[derive(Debug, Default)]
+struct ProfilerState {
+    stacks: HashMap<ThreadId, Vec<OpenScope>>,
+    finished: Vec<ProfileScope>,
+}
+
+/// A CPU span profiler: a layer to add to a [`tracing_subscriber::Registry`]
+/// that collects the spans entered by wgpu-core into a hierarchical tree of
+/// CPU timings.
+///
+/// Only the time spent on the CPU is measured. GPU timings are not collected,
+/// since wgpu-core doesn't support timestamp queries yet.
+///
+/// Call [`ProfilerLayer::end_frame`] once per frame to take the top-level scopes
+/// that were completed since the last call.
+#[derive(Clone, Debug, Default)]
+pub struct ProfilerLayer {
+    state: Arc<Mutex<ProfilerState>>,
+}
+
+impl ProfilerLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take all the top-level scopes completed since the last call, in order of completion.
+    pub fn end_frame(&self) -> Vec<ProfileScope> {
+        std::mem::replace(&mut self.state.lock().unwrap().finished, Vec::new())
+    }
+}
+
+impl<S> Layer<S> for ProfilerLayer
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let span = ctx.span(id).unwrap();
+        let mut state = self.state.lock().unwrap();
+        state
+            .stacks
+            .entry(thread::current().id())
+            .or_insert_with(Vec::new)
+            .push(OpenScope {
+                name: span.metadata().name(),
+                start: Instant::now(),
+                children: Vec::new(),
+            });
+    }
+
+    fn on_exit(&self, _id: &span::Id, _ctx: Context<'_, S>) {
+        if thread::panicking() {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let ProfilerState {
+            ref mut stacks,
+            ref mut finished,
+        } = *state;
+        let stack = match stacks.get_mut(&thread::current().id()) {
+            Some(stack) => stack,
+            None => return,
+        };
+        let open = match stack.pop() {
+            Some(open) => open,
+            None => return,
+        };
+        let scope = ProfileScope {
+            name: open.name,
+            cpu_time: open.start.elapsed(),
+            children: open.children,
+        };
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(scope),
+            None => finished.push(scope),
+        }
+    }
+}
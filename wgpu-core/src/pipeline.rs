@@ -67,6 +67,8 @@ pub enum RenderPipelineError {
         index: u8,
     },
     InvalidSampleCount(u32),
+    PrimitiveRestartWithList(wgt::PrimitiveTopology),
+    MissingFeature(wgt::Features),
 }
 
 bitflags::bitflags! {
@@ -75,6 +77,7 @@ bitflags::bitflags! {
         const BLEND_COLOR = 1;
         const STENCIL_REFERENCE = 2;
         const DEPTH_STENCIL_READ_ONLY = 4;
+        const DEPTH_BOUNDS = 8;
     }
 }
 
@@ -88,6 +88,13 @@ pub(crate) enum BufferMapState<B: hal::Backend> {
         sub_range: hal::buffer::SubRange,
         host: crate::device::HostMap,
     },
+    /// Mapped for the whole lifetime of the buffer
+    Persistent {
+        ptr: NonNull<u8>,
+        host: crate::device::HostMap,
+        /// Mapped range of the memory, `None` if the memory is coherent.
+        sync_segment: Option<hal::memory::Segment>,
+    },
     /// Not mapped
     Idle,
 }
@@ -30,6 +30,27 @@ impl PendingTransition<TextureState> {
             Err(self)
         }
     }
+
+    /// Try to extend `self` by the `other` transition of the same texture,
+    /// if it covers the adjacent mip levels or array layers with the same usage.
+    pub(crate) fn try_merge(&mut self, other: &Self) -> bool {
+        if self.id != other.id || self.usage != other.usage {
+            return false;
+        }
+        if self.selector.layers == other.selector.layers
+            && self.selector.levels.end == other.selector.levels.start
+        {
+            self.selector.levels.end = other.selector.levels.end;
+            true
+        } else if self.selector.levels == other.selector.levels
+            && self.selector.layers.end == other.selector.layers.start
+        {
+            self.selector.layers.end = other.selector.layers.end;
+            true
+        } else {
+            false
+        }
+    }
 }
 
 impl TextureState {
@@ -274,6 +295,37 @@ mod test {
     use crate::id::Id;
     use hal::{format::Aspects, image::SubresourceRange};
 
+    #[test]
+    fn merge_transitions() {
+        let id = Id::dummy();
+        let transition = |levels, layers| PendingTransition {
+            id,
+            selector: SubresourceRange {
+                aspects: Aspects::empty(),
+                levels,
+                layers,
+            },
+            usage: TextureUse::SAMPLED..TextureUse::COPY_DST,
+        };
+
+        let mut pending = transition(0..1, 0..2);
+        assert!(pending.try_merge(&transition(1..2, 0..2)));
+        assert_eq!(pending, transition(0..2, 0..2));
+        assert!(pending.try_merge(&transition(0..2, 2..3)));
+        assert_eq!(pending, transition(0..2, 0..3));
+        assert!(
+            !pending.try_merge(&transition(2..3, 0..1)),
+            "merged transitions with different layers"
+        );
+        assert!(
+            !pending.try_merge(&PendingTransition {
+                usage: TextureUse::COPY_SRC..TextureUse::COPY_DST,
+                ..transition(2..3, 0..3)
+            }),
+            "merged transitions with different usage"
+        );
+    }
+
     #[test]
     fn query() {
         let mut ts = TextureState::default();
@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::{binding_model::BindEntryMap, FastHashMap};
+use crate::{binding_model::BindEntryMap, span, FastHashMap};
 use spirv_headers as spirv;
 use wgt::{BindGroupLayoutEntry, BindingType};
 
@@ -57,6 +57,16 @@ pub enum StageError {
     },
 }
 
+/// Errors produced when validating a shader module against the device.
+#[derive(Clone, Debug)]
+pub enum ModuleError {
+    /// A type used by the module requires a feature that isn't enabled.
+    MissingFeature {
+        ty: naga::Handle<naga::Type>,
+        feature: wgt::Features,
+    },
+}
+
 fn get_aligned_type_size(
     module: &naga::Module,
     handle: naga::Handle<naga::Type>,
@@ -610,6 +620,43 @@ pub fn check_texture_format(format: wgt::TextureFormat, output: &naga::TypeInner
     is_sub_type(&required, output)
 }
 
+/// Check that all the types used by the `module` are supported by the enabled `features`.
+pub fn check_module(module: &naga::Module, features: wgt::Features) -> Result<(), ModuleError> {
+    use naga::{ScalarKind as Sk, TypeInner as Ti};
+    span!(_guard, DEBUG, "validation::check_module");
+
+    for (handle, ty) in module.types.iter() {
+        let required = match ty.inner {
+            Ti::Scalar {
+                kind: Sk::Sint,
+                width: 16,
+            }
+            | Ti::Scalar {
+                kind: Sk::Uint,
+                width: 16,
+            }
+            | Ti::Vector {
+                kind: Sk::Sint,
+                width: 16,
+                ..
+            }
+            | Ti::Vector {
+                kind: Sk::Uint,
+                width: 16,
+                ..
+            } => wgt::Features::SHADER_INT16,
+            _ => wgt::Features::empty(),
+        };
+        if !features.contains(required) {
+            return Err(ModuleError::MissingFeature {
+                ty: handle,
+                feature: required - features,
+            });
+        }
+    }
+    Ok(())
+}
+
 pub type StageInterface<'a> = FastHashMap<wgt::ShaderLocation, MaybeOwned<'a, naga::TypeInner>>;
 
 pub fn check_stage<'a>(
@@ -619,6 +666,8 @@ pub fn check_stage<'a>(
     execution_model: spirv::ExecutionModel,
     inputs: StageInterface<'a>,
 ) -> Result<StageInterface<'a>, StageError> {
+    span!(_guard, DEBUG, "validation::check_stage");
+
     // Since a shader module can have multiple entry points with the same name,
     // we need to look for one with the right execution model.
     let entry_point = module
@@ -115,6 +115,10 @@ pub struct RequestAdapterOptions<S> {
     /// Surface that is required to be presentable with the requested adapter. This does not
     /// create the surface, only guarantees that the adapter can present to said surface.
     pub compatible_surface: Option<S>,
+    /// Only consider software adapters that run on the CPU, such as WARP on DX12.
+    /// This allows running on machines that have no GPU at all.
+    #[cfg_attr(feature = "replay", serde(default))]
+    pub force_fallback_adapter: bool,
 }
 
 bitflags::bitflags! {
@@ -241,6 +245,67 @@ bitflags::bitflags! {
         ///
         /// This is a native only feature.
         const PUSH_CONSTANTS = 0x0000_0000_0080_0000;
+        /// Allows shaders to use 16-bit signed and unsigned integer types, both
+        /// for arithmetic and as members of uniform and storage buffer structures.
+        ///
+        /// Halves the memory bandwidth of index-heavy compute workloads compared to
+        /// widening everything to 32 bits.
+        ///
+        /// Supported platforms:
+        /// - DX12 (with Shader Model 6.2)
+        /// - Vulkan's shaderInt16 feature
+        /// - Metal
+        ///
+        /// This is a native only feature.
+        const SHADER_INT16 = 0x0000_0000_0100_0000;
+        /// Allows buffers with [`BufferUsage::PERSISTENT_MAP`] usage, which stay mapped
+        /// for their whole lifetime, including while the GPU uses them.
+        ///
+        /// The user is responsible for synchronizing the host access with the GPU,
+        /// and for flushing the host writes and invalidating the ranges before host reads,
+        /// unless the memory is coherent.
+        ///
+        /// Supported platforms:
+        /// - All
+        ///
+        /// This is a native only feature.
+        const PERSISTENT_MAPPING = 0x0000_0000_0200_0000;
+        /// Allows setting the viewports and scissor rectangles with a non-zero index.
+        /// The primitives select the viewport with the `ViewportIndex` output
+        /// of the last vertex processing stage.
+        ///
+        /// Supported platforms:
+        /// - Vulkan
+        /// - DX12
+        /// - Metal (macOS only)
+        ///
+        /// This is a native only feature.
+        const MULTI_VIEWPORT = 0x0000_0000_0400_0000;
+        /// Allows the `first_instance` of the indirect draw arguments to be non-zero.
+        ///
+        /// Without it, the instance index of indirect draws starts at zero on some platforms
+        /// and at `first_instance` on others, so the arguments must contain zero there.
+        /// Since the arguments are usually written by the GPU, this is not validated.
+        ///
+        /// Supported platforms:
+        /// - Vulkan (with drawIndirectFirstInstance)
+        /// - DX12
+        /// - Metal
+        ///
+        /// This is a native only feature.
+        const INDIRECT_FIRST_INSTANCE = 0x0000_0000_0800_0000;
+        /// Allows the depth bounds test to be enabled in the depth/stencil state
+        /// of render pipelines, and the bounds to be set with `set_depth_bounds`.
+        ///
+        /// Fragments whose existing depth value is outside of the bounds are discarded,
+        /// which lets deferred renderers skip light volumes that can't affect any pixel.
+        ///
+        /// Supported platforms:
+        /// - Vulkan (with depthBounds)
+        /// - DX12 (with DepthBoundsTestSupported)
+        ///
+        /// This is a native only feature.
+        const DEPTH_BOUNDS_TEST = 0x0000_0000_1000_0000;
         /// Features which are part of the upstream WebGPU standard.
         const ALL_WEBGPU = 0x0000_0000_0000_FFFF;
         /// Features that are only available when targeting native (not web).
@@ -312,6 +377,93 @@ impl Default for Limits {
     }
 }
 
+impl Limits {
+    fn named_values(&self) -> [(&'static str, u32); 10] {
+        [
+            ("max_bind_groups", self.max_bind_groups),
+            (
+                "max_dynamic_uniform_buffers_per_pipeline_layout",
+                self.max_dynamic_uniform_buffers_per_pipeline_layout,
+            ),
+            (
+                "max_dynamic_storage_buffers_per_pipeline_layout",
+                self.max_dynamic_storage_buffers_per_pipeline_layout,
+            ),
+            (
+                "max_sampled_textures_per_shader_stage",
+                self.max_sampled_textures_per_shader_stage,
+            ),
+            (
+                "max_samplers_per_shader_stage",
+                self.max_samplers_per_shader_stage,
+            ),
+            (
+                "max_storage_buffers_per_shader_stage",
+                self.max_storage_buffers_per_shader_stage,
+            ),
+            (
+                "max_storage_textures_per_shader_stage",
+                self.max_storage_textures_per_shader_stage,
+            ),
+            (
+                "max_uniform_buffers_per_shader_stage",
+                self.max_uniform_buffers_per_shader_stage,
+            ),
+            (
+                "max_uniform_buffer_binding_size",
+                self.max_uniform_buffer_binding_size,
+            ),
+            ("max_push_constant_size", self.max_push_constant_size),
+        ]
+    }
+
+    /// Find the first limit that is "better" than the one `allowed`.
+    ///
+    /// Returns the name of the limit, the requested value, and the allowed one.
+    pub fn first_exceeding(&self, allowed: &Self) -> Option<(&'static str, u32, u32)> {
+        self.named_values()
+            .iter()
+            .zip(allowed.named_values().iter())
+            .find(|&(&(_, requested), &(_, max))| requested > max)
+            .map(|(&(name, requested), &(_, max))| (name, requested, max))
+    }
+
+    /// Lower the limits that are "better" than the ones `allowed`,
+    /// e.g. the limits of an adapter, to the allowed values.
+    pub fn clamped_to(&self, allowed: &Self) -> Self {
+        Limits {
+            max_bind_groups: self.max_bind_groups.min(allowed.max_bind_groups),
+            max_dynamic_uniform_buffers_per_pipeline_layout: self
+                .max_dynamic_uniform_buffers_per_pipeline_layout
+                .min(allowed.max_dynamic_uniform_buffers_per_pipeline_layout),
+            max_dynamic_storage_buffers_per_pipeline_layout: self
+                .max_dynamic_storage_buffers_per_pipeline_layout
+                .min(allowed.max_dynamic_storage_buffers_per_pipeline_layout),
+            max_sampled_textures_per_shader_stage: self
+                .max_sampled_textures_per_shader_stage
+                .min(allowed.max_sampled_textures_per_shader_stage),
+            max_samplers_per_shader_stage: self
+                .max_samplers_per_shader_stage
+                .min(allowed.max_samplers_per_shader_stage),
+            max_storage_buffers_per_shader_stage: self
+                .max_storage_buffers_per_shader_stage
+                .min(allowed.max_storage_buffers_per_shader_stage),
+            max_storage_textures_per_shader_stage: self
+                .max_storage_textures_per_shader_stage
+                .min(allowed.max_storage_textures_per_shader_stage),
+            max_uniform_buffers_per_shader_stage: self
+                .max_uniform_buffers_per_shader_stage
+                .min(allowed.max_uniform_buffers_per_shader_stage),
+            max_uniform_buffer_binding_size: self
+                .max_uniform_buffer_binding_size
+                .min(allowed.max_uniform_buffer_binding_size),
+            max_push_constant_size: self
+                .max_push_constant_size
+                .min(allowed.max_push_constant_size),
+        }
+    }
+}
+
 /// Describes a [`Device`].
 #[repr(C)]
 #[derive(Clone, Debug, Default)]
@@ -321,12 +473,20 @@ pub struct DeviceDescriptor {
     /// Features that the device should support. If any feature is not supported by
     /// the adapter, creating a device will panic.
     pub features: Features,
+    /// Features that are enabled only if the adapter supports them. The features that
+    /// ended up enabled can be queried from the device.
+    #[cfg_attr(feature = "replay", serde(default))]
+    pub optional_features: Features,
     /// Limits that the device should support. If any limit is "better" than the limit exposed by
     /// the adapter, creating a device will panic.
     pub limits: Limits,
     /// Switch shader validation on/off. This is a temporary field
     /// that will be removed once our validation logic is complete.
     pub shader_validation: bool,
+    /// Report the usage that is valid, but behaves differently across the backends,
+    /// or isn't supported by WebGPU on the Web. The reports are logged as warnings.
+    #[cfg_attr(feature = "replay", serde(default))]
+    pub portability_checks: bool,
 }
 
 bitflags::bitflags! {
@@ -695,6 +855,12 @@ pub struct DepthStencilStateDescriptor {
     pub stencil_read_mask: u32,
     /// Stencil values are AND'd with this mask when writing to the stencil buffer. Only low 8 bits are used.
     pub stencil_write_mask: u32,
+    /// If enabled, fragments are discarded when the depth value already in the buffer
+    /// is outside of the bounds set by `set_depth_bounds`.
+    ///
+    /// Requires [`Features::DEPTH_BOUNDS_TEST`].
+    #[cfg_attr(feature = "replay", serde(default))]
+    pub depth_bounds_test_enabled: bool,
 }
 
 impl DepthStencilStateDescriptor {
@@ -871,6 +1037,9 @@ pub struct VertexStateDescriptor<'a> {
     pub index_format: IndexFormat,
     /// The format of any vertex buffers used with this pipeline.
     pub vertex_buffers: &'a [VertexBufferDescriptor<'a>],
+    /// Restart the strip when the maximum value of the `index_format` is encountered
+    /// in an indexed draw. Only allowed with strip topologies.
+    pub primitive_restart: bool,
 }
 
 /// Vertex Format for a Vertex Attribute (input).
@@ -978,6 +1147,11 @@ bitflags::bitflags! {
         const STORAGE = 128;
         /// Allow a buffer to be the indirect buffer in an indirect draw call.
         const INDIRECT = 256;
+        /// Keep a buffer mapped for its whole lifetime. Has to be combined with either
+        /// [`BufferUsage::MAP_READ`] or [`BufferUsage::MAP_WRITE`].
+        ///
+        /// Requires [`Features::PERSISTENT_MAPPING`].
+        const PERSISTENT_MAP = 512;
     }
 }
 
@@ -1072,6 +1246,10 @@ bitflags::bitflags! {
         const STORAGE = 8;
         /// Allows a texture to be a output attachment of a renderpass.
         const OUTPUT_ATTACHMENT = 16;
+        /// Hints that the contents of the texture never need to outlive a render pass,
+        /// allowing it to stay in the on-chip memory of tile-based GPUs.
+        /// Can only be combined with [`OUTPUT_ATTACHMENT`].
+        const TRANSIENT = 32;
     }
 }
 
@@ -1840,3 +2018,65 @@ pub struct TextureCopyView<T> {
     /// The base texel of the texture in the selected `mip_level`.
     pub origin: Origin3d,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn raised_limits() -> Limits {
+        Limits {
+            max_bind_groups: 8,
+            max_dynamic_uniform_buffers_per_pipeline_layout: 16,
+            max_dynamic_storage_buffers_per_pipeline_layout: 8,
+            max_sampled_textures_per_shader_stage: 32,
+            max_samplers_per_shader_stage: 32,
+            max_storage_buffers_per_shader_stage: 8,
+            max_storage_textures_per_shader_stage: 8,
+            max_uniform_buffers_per_shader_stage: 24,
+            max_uniform_buffer_binding_size: 65536,
+            max_push_constant_size: 128,
+        }
+    }
+
+    #[test]
+    fn first_exceeding_none() {
+        let allowed = Limits::default();
+        assert_eq!(Limits::default().first_exceeding(&allowed), None);
+        assert_eq!(allowed.first_exceeding(&raised_limits()), None);
+    }
+
+    #[test]
+    fn first_exceeding_single() {
+        let requested = Limits {
+            max_storage_buffers_per_shader_stage: 5,
+            ..Limits::default()
+        };
+        assert_eq!(
+            requested.first_exceeding(&Limits::default()),
+            Some(("max_storage_buffers_per_shader_stage", 5, 4))
+        );
+    }
+
+    #[test]
+    fn clamped_to_lowers_each_limit() {
+        let allowed = Limits::default();
+        assert_eq!(raised_limits().clamped_to(&allowed), allowed);
+    }
+
+    #[test]
+    fn clamped_to_keeps_lower_limits() {
+        let requested = Limits {
+            max_bind_groups: 1,
+            max_dynamic_uniform_buffers_per_pipeline_layout: 1,
+            max_dynamic_storage_buffers_per_pipeline_layout: 1,
+            max_sampled_textures_per_shader_stage: 1,
+            max_samplers_per_shader_stage: 1,
+            max_storage_buffers_per_shader_stage: 1,
+            max_storage_textures_per_shader_stage: 1,
+            max_uniform_buffers_per_shader_stage: 1,
+            max_uniform_buffer_binding_size: 1,
+            max_push_constant_size: 1,
+        };
+        assert_eq!(requested.clamped_to(&raised_limits()), requested);
+    }
+}